@@ -0,0 +1,6 @@
+begin;
+    alter table poop.auth_tokens
+        drop column user_agent,
+        drop column ip,
+        drop column last_seen;
+commit;
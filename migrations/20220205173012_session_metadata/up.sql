@@ -0,0 +1,6 @@
+begin;
+    alter table poop.auth_tokens
+        add column user_agent text,
+        add column ip         text,
+        add column last_seen  timestamptz not null default now();
+commit;
@@ -0,0 +1,3 @@
+begin;
+    drop table poop.password_reset_tokens;
+commit;
@@ -0,0 +1,15 @@
+begin;
+    create table poop.password_reset_tokens (
+        id       bigint primary key default poop.id_gen(),
+        user_id  bigint not null references poop.users(id) on delete cascade,
+        hash     text unique not null,
+        expires  timestamptz not null,
+        deleted  boolean not null default false,
+        created  timestamptz not null default now(),
+        modified timestamptz not null default now()
+    );
+    create index idx_password_reset_tokens_user_id on poop.password_reset_tokens(user_id)
+        where deleted is false;
+    create index idx_password_reset_tokens_hash on poop.password_reset_tokens(hash)
+        where deleted is false;
+commit;
@@ -0,0 +1,3 @@
+begin;
+    drop table poop.email_verifications;
+commit;
@@ -0,0 +1,16 @@
+begin;
+    create table poop.email_verifications (
+        id       bigint primary key default poop.id_gen(),
+        user_id  bigint not null references poop.users(id) on delete cascade,
+        email    text not null,
+        hash     text unique not null,
+        expires  timestamptz not null,
+        deleted  boolean not null default false,
+        created  timestamptz not null default now(),
+        modified timestamptz not null default now()
+    );
+    create index idx_email_verifications_user_id on poop.email_verifications(user_id)
+        where deleted is false;
+    create index idx_email_verifications_hash on poop.email_verifications(hash)
+        where deleted is false;
+commit;
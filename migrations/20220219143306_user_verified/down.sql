@@ -0,0 +1,3 @@
+begin;
+    alter table poop.users drop column verified_at;
+commit;
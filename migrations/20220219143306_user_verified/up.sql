@@ -0,0 +1,5 @@
+begin;
+    alter table poop.users add column verified_at timestamptz;
+    -- everyone who signed up before verification existed is grandfathered in
+    update poop.users set verified_at = created;
+commit;
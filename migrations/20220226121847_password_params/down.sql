@@ -0,0 +1,3 @@
+begin;
+    alter table poop.users drop column pw_params;
+commit;
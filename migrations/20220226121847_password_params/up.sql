@@ -0,0 +1,7 @@
+begin;
+    -- existing hashes were all made with pbkdf2-sha512 @ 100k iterations
+    alter table poop.users
+        add column pw_params jsonb not null
+            default '{"alg": "pbkdf2-sha512", "iterations": 100000}';
+    alter table poop.users alter column pw_params drop default;
+commit;
@@ -0,0 +1,3 @@
+begin;
+    drop table poop.login_attempts;
+commit;
@@ -0,0 +1,8 @@
+begin;
+    create table poop.login_attempts (
+        key          text primary key,
+        failures     integer not null,
+        last_failure timestamptz not null
+    );
+    create index idx_login_attempts_last_failure on poop.login_attempts(last_failure);
+commit;
@@ -0,0 +1,8 @@
+begin;
+    drop table poop.login_challenges;
+    drop table poop.totp_recovery_codes;
+    alter table poop.users
+        drop column totp_secret,
+        drop column totp_enabled_at,
+        drop column totp_last_step;
+commit;
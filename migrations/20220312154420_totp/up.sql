@@ -0,0 +1,32 @@
+begin;
+    alter table poop.users
+        -- crypto::Enc of the base32 secret, set once setup starts
+        add column totp_secret     jsonb,
+        -- null until the secret has been confirmed with a code
+        add column totp_enabled_at timestamptz,
+        -- last time step a code was accepted for, so codes can't be replayed
+        add column totp_last_step  bigint;
+
+    create table poop.totp_recovery_codes (
+        id       bigint primary key default poop.id_gen(),
+        user_id  bigint not null references poop.users(id) on delete cascade,
+        hash     text not null,
+        deleted  boolean not null default false,
+        created  timestamptz not null default now(),
+        modified timestamptz not null default now()
+    );
+    create index idx_totp_recovery_codes_user_id on poop.totp_recovery_codes(user_id)
+        where deleted is false;
+
+    create table poop.login_challenges (
+        id       bigint primary key default poop.id_gen(),
+        user_id  bigint not null references poop.users(id) on delete cascade,
+        hash     text unique not null,
+        expires  timestamptz not null,
+        deleted  boolean not null default false,
+        created  timestamptz not null default now(),
+        modified timestamptz not null default now()
+    );
+    create index idx_login_challenges_hash on poop.login_challenges(hash)
+        where deleted is false;
+commit;
@@ -0,0 +1,4 @@
+begin;
+    drop table poop.api_tokens;
+    drop table poop.api_token_scope;
+commit;
@@ -0,0 +1,25 @@
+begin;
+    create table poop.api_token_scope (
+        scope text primary key
+    );
+    insert into poop.api_token_scope (scope) values
+        ('read'),
+        ('write');
+
+    create table poop.api_tokens (
+        id        bigint primary key default poop.id_gen(),
+        user_id   bigint not null references poop.users(id) on delete cascade,
+        name      text not null,
+        hash      text unique not null,
+        scope     text not null references poop.api_token_scope(scope),
+        expires   timestamptz,
+        last_used timestamptz,
+        deleted   boolean not null default false,
+        created   timestamptz not null default now(),
+        modified  timestamptz not null default now()
+    );
+    create index idx_api_tokens_user_id on poop.api_tokens(user_id)
+        where deleted is false;
+    create index idx_api_tokens_hash on poop.api_tokens(hash)
+        where deleted is false;
+commit;
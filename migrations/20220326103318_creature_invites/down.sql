@@ -0,0 +1,4 @@
+begin;
+    drop table poop.creature_invites;
+    drop index poop.idx_creature_access_creature_user;
+commit;
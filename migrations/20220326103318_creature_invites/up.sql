@@ -0,0 +1,25 @@
+begin;
+    create unique index idx_creature_access_creature_user on poop.creature_access(creature_id, user_id)
+        where deleted is false;
+
+    create table poop.creature_invites (
+        id          bigint primary key default poop.id_gen(),
+        creature_id bigint not null references poop.creatures(id),
+        user_id     bigint not null references poop.users(id) on delete cascade,
+        creator_id  bigint not null references poop.users(id),
+        kind        text not null references poop.creature_access_kind(kind),
+        hash        text unique not null,
+        expires     timestamptz not null,
+        accepted_at timestamptz,
+        declined_at timestamptz,
+        deleted     boolean not null default false,
+        created     timestamptz not null default now(),
+        modified    timestamptz not null default now()
+    );
+    create unique index idx_creature_invites_creature_user on poop.creature_invites(creature_id, user_id)
+        where deleted is false;
+    create index idx_creature_invites_user on poop.creature_invites(user_id)
+        where deleted is false;
+    create index idx_creature_invites_hash on poop.creature_invites(hash)
+        where deleted is false;
+commit;
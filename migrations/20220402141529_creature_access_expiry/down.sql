@@ -0,0 +1,4 @@
+begin;
+    alter table poop.creature_access drop column expires;
+    alter table poop.creature_access drop column starts;
+commit;
@@ -0,0 +1,8 @@
+begin;
+    alter table poop.creature_access add column starts timestamptz;
+    alter table poop.creature_access add column expires timestamptz;
+    alter table poop.creature_access add constraint creature_access_starts_before_expires
+        check (starts is null or expires is null or starts < expires);
+    create index idx_creature_access_expires on poop.creature_access(expires)
+        where deleted is false and expires is not null;
+commit;
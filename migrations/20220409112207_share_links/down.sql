@@ -0,0 +1,3 @@
+begin;
+    drop table poop.share_links;
+commit;
@@ -0,0 +1,18 @@
+begin;
+    create table poop.share_links (
+        id          bigint primary key default poop.id_gen(),
+        creature_id bigint not null references poop.creatures(id),
+        creator_id  bigint not null references poop.users(id),
+        name        text not null,
+        hash        text unique not null,
+        expires     timestamptz,
+        last_viewed timestamptz,
+        deleted     boolean not null default false,
+        created     timestamptz not null default now(),
+        modified    timestamptz not null default now()
+    );
+    create index idx_share_links_creature on poop.share_links(creature_id)
+        where deleted is false;
+    create index idx_share_links_hash on poop.share_links(hash)
+        where deleted is false;
+commit;
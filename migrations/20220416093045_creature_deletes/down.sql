@@ -0,0 +1,17 @@
+begin;
+    alter table poop.creatures drop column deleted_at;
+
+    drop trigger set_api_tokens_modified on poop.api_tokens;
+    drop trigger set_auth_tokens_modified on poop.auth_tokens;
+    drop trigger set_creature_access_modified on poop.creature_access;
+    drop trigger set_creature_invites_modified on poop.creature_invites;
+    drop trigger set_creatures_modified on poop.creatures;
+    drop trigger set_email_verifications_modified on poop.email_verifications;
+    drop trigger set_login_challenges_modified on poop.login_challenges;
+    drop trigger set_password_reset_tokens_modified on poop.password_reset_tokens;
+    drop trigger set_poops_modified on poop.poops;
+    drop trigger set_share_links_modified on poop.share_links;
+    drop trigger set_totp_recovery_codes_modified on poop.totp_recovery_codes;
+    drop trigger set_users_modified on poop.users;
+    drop function poop.set_modified();
+commit;
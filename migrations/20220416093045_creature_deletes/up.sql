@@ -0,0 +1,36 @@
+begin;
+    alter table poop.creatures add column deleted_at timestamptz;
+    update poop.creatures set deleted_at = modified where deleted is true;
+
+    create function poop.set_modified() returns trigger as $$
+    begin
+        new.modified = now();
+        return new;
+    end;
+    $$ language plpgsql;
+
+    create trigger set_api_tokens_modified before update on poop.api_tokens
+        for each row execute function poop.set_modified();
+    create trigger set_auth_tokens_modified before update on poop.auth_tokens
+        for each row execute function poop.set_modified();
+    create trigger set_creature_access_modified before update on poop.creature_access
+        for each row execute function poop.set_modified();
+    create trigger set_creature_invites_modified before update on poop.creature_invites
+        for each row execute function poop.set_modified();
+    create trigger set_creatures_modified before update on poop.creatures
+        for each row execute function poop.set_modified();
+    create trigger set_email_verifications_modified before update on poop.email_verifications
+        for each row execute function poop.set_modified();
+    create trigger set_login_challenges_modified before update on poop.login_challenges
+        for each row execute function poop.set_modified();
+    create trigger set_password_reset_tokens_modified before update on poop.password_reset_tokens
+        for each row execute function poop.set_modified();
+    create trigger set_poops_modified before update on poop.poops
+        for each row execute function poop.set_modified();
+    create trigger set_share_links_modified before update on poop.share_links
+        for each row execute function poop.set_modified();
+    create trigger set_totp_recovery_codes_modified before update on poop.totp_recovery_codes
+        for each row execute function poop.set_modified();
+    create trigger set_users_modified before update on poop.users
+        for each row execute function poop.set_modified();
+commit;
@@ -0,0 +1,3 @@
+begin;
+    alter table poop.poops drop column occurred_at;
+commit;
@@ -0,0 +1,8 @@
+begin;
+    alter table poop.poops add column occurred_at timestamptz;
+    update poop.poops set occurred_at = created;
+    alter table poop.poops alter column occurred_at set not null;
+    alter table poop.poops alter column occurred_at set default now();
+    create index idx_poop_creature_occurred_at on poop.poops(creature_id, occurred_at)
+        where deleted is false;
+commit;
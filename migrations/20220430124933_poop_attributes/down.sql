@@ -0,0 +1,10 @@
+begin;
+    alter table poop.poops drop column notes;
+    alter table poop.poops drop column mucus;
+    alter table poop.poops drop column blood;
+    alter table poop.poops drop column size;
+    alter table poop.poops drop column color;
+    alter table poop.poops drop column bristol;
+    drop table poop.poop_size;
+    drop table poop.poop_color;
+commit;
@@ -0,0 +1,30 @@
+begin;
+    create table poop.poop_color (
+        color text primary key
+    );
+    insert into poop.poop_color (color) values
+        ('brown'),
+        ('dark_brown'),
+        ('yellow'),
+        ('green'),
+        ('orange'),
+        ('red'),
+        ('black'),
+        ('gray');
+
+    create table poop.poop_size (
+        size text primary key
+    );
+    insert into poop.poop_size (size) values
+        ('small'),
+        ('medium'),
+        ('large');
+
+    alter table poop.poops add column bristol smallint
+        check (bristol between 1 and 7);
+    alter table poop.poops add column color text references poop.poop_color(color);
+    alter table poop.poops add column size text references poop.poop_size(size);
+    alter table poop.poops add column blood boolean not null default false;
+    alter table poop.poops add column mucus boolean not null default false;
+    alter table poop.poops add column notes text;
+commit;
@@ -0,0 +1,63 @@
+begin;
+    drop view poop.poops;
+
+    create table poop.poop_color (
+        color text primary key
+    );
+    insert into poop.poop_color (color) values
+        ('brown'),
+        ('dark_brown'),
+        ('yellow'),
+        ('green'),
+        ('orange'),
+        ('red'),
+        ('black'),
+        ('gray');
+
+    create table poop.poop_size (
+        size text primary key
+    );
+    insert into poop.poop_size (size) values
+        ('small'),
+        ('medium'),
+        ('large');
+
+    create table poop.poops (
+        id          bigint primary key default poop.id_gen(),
+        creator_id  bigint not null references poop.users(id),
+        creature_id bigint not null references poop.creatures(id),
+        occurred_at timestamptz not null default now(),
+        bristol     smallint check (bristol between 1 and 7),
+        color       text references poop.poop_color(color),
+        size        text references poop.poop_size(size),
+        blood       boolean not null default false,
+        mucus       boolean not null default false,
+        notes       text,
+        deleted     boolean not null default false,
+        created     timestamptz not null default now(),
+        modified    timestamptz not null default now()
+    );
+    create index idx_poop_creator on poop.poops(creator_id)
+        where deleted is false;
+    create index idx_poop_creature on poop.poops(creature_id)
+        where deleted is false;
+    create index idx_poop_creature_occurred_at on poop.poops(creature_id, occurred_at)
+        where deleted is false;
+    create trigger set_poops_modified before update on poop.poops
+        for each row execute function poop.set_modified();
+
+    insert into poop.poops
+        (id, creator_id, creature_id, occurred_at, bristol, color, size, blood, mucus, notes, deleted, created, modified)
+        select id, creator_id, creature_id, occurred_at,
+            (attrs->>'bristol')::smallint,
+            attrs->>'color',
+            attrs->>'size',
+            coalesce((attrs->>'blood')::boolean, false),
+            coalesce((attrs->>'mucus')::boolean, false),
+            notes, deleted, created, modified
+        from poop.events
+        where kind = 'poop';
+
+    drop table poop.events;
+    drop table poop.event_kind;
+commit;
@@ -0,0 +1,121 @@
+begin;
+    -- attrs of each event are validated against its kind's schema,
+    -- a subset of JSON schema
+    create table poop.event_kind (
+        kind         text primary key,
+        attrs_schema jsonb not null default '{"type": "object"}'
+    );
+    insert into poop.event_kind (kind, attrs_schema) values
+        ('poop', '{
+            "type": "object",
+            "properties": {
+                "bristol": {"type": "integer", "minimum": 1, "maximum": 7},
+                "color": {"type": "string", "enum": ["brown", "dark_brown", "yellow", "green", "orange", "red", "black", "gray"]},
+                "size": {"type": "string", "enum": ["small", "medium", "large"]},
+                "blood": {"type": "boolean"},
+                "mucus": {"type": "boolean"}
+            },
+            "additionalProperties": false
+        }'),
+        ('pee', '{
+            "type": "object",
+            "properties": {
+                "color": {"type": "string", "enum": ["clear", "pale_yellow", "yellow", "dark_yellow", "amber", "red"]},
+                "size": {"type": "string", "enum": ["small", "medium", "large"]},
+                "blood": {"type": "boolean"}
+            },
+            "additionalProperties": false
+        }'),
+        ('vomit', '{
+            "type": "object",
+            "properties": {
+                "contents": {"type": "string", "enum": ["food", "bile", "liquid", "foam", "hairball", "other"]},
+                "size": {"type": "string", "enum": ["small", "medium", "large"]},
+                "blood": {"type": "boolean"}
+            },
+            "additionalProperties": false
+        }'),
+        ('feed', '{
+            "type": "object",
+            "properties": {
+                "food": {"type": "string", "minLength": 1, "maxLength": 200},
+                "method": {"type": "string", "enum": ["breast", "bottle", "bowl", "hand"]},
+                "amount": {"type": "number", "minimum": 0},
+                "unit": {"type": "string", "enum": ["ml", "oz", "g", "kg", "lb", "cup", "tbsp", "tsp", "piece"]}
+            },
+            "additionalProperties": false
+        }'),
+        ('sleep', '{
+            "type": "object",
+            "properties": {
+                "duration_minutes": {"type": "integer", "minimum": 0, "maximum": 1440},
+                "quality": {"type": "string", "enum": ["poor", "fair", "good"]}
+            },
+            "additionalProperties": false
+        }'),
+        ('meds', '{
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "minLength": 1, "maxLength": 200},
+                "dose": {"type": "number", "minimum": 0},
+                "unit": {"type": "string", "maxLength": 20}
+            },
+            "required": ["name"],
+            "additionalProperties": false
+        }');
+
+    create table poop.events (
+        id          bigint primary key default poop.id_gen(),
+        creator_id  bigint not null references poop.users(id),
+        creature_id bigint not null references poop.creatures(id),
+        kind        text not null references poop.event_kind(kind),
+        occurred_at timestamptz not null default now(),
+        attrs       jsonb not null default '{}',
+        notes       text,
+        deleted     boolean not null default false,
+        created     timestamptz not null default now(),
+        modified    timestamptz not null default now()
+    );
+    create index idx_events_creator on poop.events(creator_id)
+        where deleted is false;
+    create index idx_events_creature_kind_occurred_at on poop.events(creature_id, kind, occurred_at)
+        where deleted is false;
+    create trigger set_events_modified before update on poop.events
+        for each row execute function poop.set_modified();
+
+    insert into poop.events
+        (id, creator_id, creature_id, kind, occurred_at, attrs, notes, deleted, created, modified)
+        select id, creator_id, creature_id, 'poop', occurred_at,
+            jsonb_strip_nulls(jsonb_build_object(
+                'bristol', bristol,
+                'color', color,
+                'size', size,
+                'blood', blood,
+                'mucus', mucus
+            )),
+            notes, deleted, created, modified
+        from poop.poops;
+
+    drop table poop.poops;
+    drop table poop.poop_color;
+    drop table poop.poop_size;
+
+    -- poops used to have their own table
+    create view poop.poops as
+        select
+            id,
+            creator_id,
+            creature_id,
+            occurred_at,
+            (attrs->>'bristol')::smallint as bristol,
+            attrs->>'color' as color,
+            attrs->>'size' as size,
+            coalesce((attrs->>'blood')::boolean, false) as blood,
+            coalesce((attrs->>'mucus')::boolean, false) as mucus,
+            notes,
+            deleted,
+            created,
+            modified
+        from poop.events
+        where kind = 'poop';
+commit;
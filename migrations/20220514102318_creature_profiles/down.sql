@@ -0,0 +1,9 @@
+begin;
+    alter table poop.creatures drop column photo_url;
+    alter table poop.creatures drop column initial_weight_grams;
+    alter table poop.creatures drop column sex;
+    alter table poop.creatures drop column birthdate;
+    alter table poop.creatures drop column breed;
+    alter table poop.creatures drop column species;
+    drop table poop.species;
+commit;
@@ -0,0 +1,28 @@
+begin;
+    -- species are data so new ones can be added without a release
+    create table poop.species (
+        species text primary key,
+        name    text not null
+    );
+    insert into poop.species (species, name) values
+        ('dog', 'Dog'),
+        ('cat', 'Cat'),
+        ('human_infant', 'Human infant'),
+        ('rabbit', 'Rabbit'),
+        ('guinea_pig', 'Guinea pig'),
+        ('hamster', 'Hamster'),
+        ('ferret', 'Ferret'),
+        ('bird', 'Bird'),
+        ('reptile', 'Reptile'),
+        ('horse', 'Horse'),
+        ('other', 'Other');
+
+    alter table poop.creatures add column species text references poop.species(species);
+    alter table poop.creatures add column breed text;
+    alter table poop.creatures add column birthdate date;
+    alter table poop.creatures add column sex text
+        check (sex in ('male', 'female'));
+    alter table poop.creatures add column initial_weight_grams double precision
+        check (initial_weight_grams > 0);
+    alter table poop.creatures add column photo_url text;
+commit;
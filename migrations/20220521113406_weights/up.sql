@@ -0,0 +1,29 @@
+begin;
+    -- weights are kept in the unit they were entered in, `grams` is
+    -- derived so they can be compared
+    create table poop.weights (
+        id          bigint primary key default poop.id_gen(),
+        creator_id  bigint not null references poop.users(id),
+        creature_id bigint not null references poop.creatures(id),
+        measured_at timestamptz not null default now(),
+        value       double precision not null check (value > 0),
+        unit        text not null check (unit in ('g', 'kg', 'lb', 'oz')),
+        grams       double precision not null generated always as (
+            value * case unit
+                when 'g' then 1
+                when 'kg' then 1000
+                when 'lb' then 453.59237
+                when 'oz' then 28.349523125
+            end
+        ) stored,
+        deleted     boolean not null default false,
+        created     timestamptz not null default now(),
+        modified    timestamptz not null default now()
+    );
+    create index idx_weights_creator on poop.weights(creator_id)
+        where deleted is false;
+    create index idx_weights_creature_measured_at on poop.weights(creature_id, measured_at)
+        where deleted is false;
+    create trigger set_weights_modified before update on poop.weights
+        for each row execute function poop.set_modified();
+commit;
@@ -0,0 +1,5 @@
+begin;
+    drop trigger set_auth_tokens_modified on poop.auth_tokens;
+    create trigger set_auth_tokens_modified before update on poop.auth_tokens
+        for each row execute function poop.set_modified();
+commit;
@@ -0,0 +1,7 @@
+begin;
+    -- `last_seen` is bookkeeping, only real changes bump `modified`
+    drop trigger set_auth_tokens_modified on poop.auth_tokens;
+    create trigger set_auth_tokens_modified
+        before update of user_id, hash, expires, deleted, user_agent, ip on poop.auth_tokens
+        for each row execute function poop.set_modified();
+commit;
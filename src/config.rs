@@ -19,6 +19,10 @@ pub struct Config {
 
     pub log_level: String,
 
+    // proxies allowed to set `x-forwarded-for`, the header is
+    // ignored for requests coming from anywhere else
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+
     // db config
     pub db_url: String,
     pub db_max_connections: u32,
@@ -29,7 +33,52 @@ pub struct Config {
     // key used for signing/hashing things
     pub signing_key: String,
 
+    // key used for signing share link tokens. Rotating it
+    // invalidates every outstanding share link.
+    pub share_link_signing_key: String,
+
+    // algorithm and cost used for new password hashes. Existing
+    // hashes are upgraded the next time their user logs in.
+    pub password_hash_alg: String,
+    pub password_pbkdf2_iterations: u32,
+    pub password_argon2_m_cost: u32,
+    pub password_argon2_t_cost: u32,
+    pub password_argon2_p_cost: u32,
+
     pub auth_expiration_seconds: u32,
+    // sessions are extended while in use, but never past this age
+    pub auth_max_session_seconds: u32,
+
+    // expired and deleted auth tokens are purged once they're older than the retention
+    pub auth_token_reap_interval_seconds: u64,
+    pub auth_token_reap_batch_size: i64,
+    pub auth_token_retention_seconds: u32,
+
+    // failed logins are tracked in "memory" or "postgres"
+    pub login_attempt_store: String,
+    // failures are forgotten once the last one is older than the window
+    pub login_attempt_window_seconds: u32,
+    pub login_max_failures_per_email: i32,
+    pub login_max_failures_per_ip: i32,
+    // lockouts start here and double with each failure, up to the max
+    pub login_lockout_seconds: u32,
+    pub login_lockout_max_seconds: u32,
+
+    pub password_reset_expiration_seconds: u32,
+    // how long a 2FA login challenge can be completed for
+    pub login_challenge_expiration_seconds: u32,
+    // shown in authenticator apps
+    pub totp_issuer: String,
+    pub email_verification_expiration_seconds: u32,
+    pub creature_invite_expiration_seconds: u32,
+    // how long a deleted creature can still be restored
+    pub creature_restore_grace_seconds: u32,
+
+    // where outgoing email goes, "log" or "file"
+    pub mailer: String,
+    // directory the "file" mailer writes to
+    pub mail_dir: String,
+    pub mail_from: String,
 }
 impl Config {
     pub fn load() -> Self {
@@ -49,16 +98,103 @@ impl Config {
             cookie_name: "poop_auth".to_string(),
             secure_cookie: env_or("SECURE_COOKIE", "true") != "false",
             log_level: env_or("LOG_LEVEL", "info"),
+            trusted_proxies: env_or("TRUSTED_PROXIES", "")
+                .split(',')
+                .map(str::trim)
+                .filter(|ip| !ip.is_empty())
+                .map(|ip| ip.parse().expect("invalid TRUSTED_PROXIES"))
+                .collect(),
             db_url: env_or("DATABASE_URL", "error"),
             db_max_connections: env_or("DATABASE_MAX_CONNECTIONS", "5")
                 .parse()
                 .expect("invalid DATABASE_MAX_CONNECTIONS"),
+            password_hash_alg: env_or("PASSWORD_HASH_ALG", "argon2id"),
+            password_pbkdf2_iterations: env_or("PASSWORD_PBKDF2_ITERATIONS", "100000")
+                .parse()
+                .expect("invalid password_pbkdf2_iterations"),
+            // 19 MiB, in KiB
+            password_argon2_m_cost: env_or("PASSWORD_ARGON2_M_COST", "19456")
+                .parse()
+                .expect("invalid password_argon2_m_cost"),
+            password_argon2_t_cost: env_or("PASSWORD_ARGON2_T_COST", "2")
+                .parse()
+                .expect("invalid password_argon2_t_cost"),
+            password_argon2_p_cost: env_or("PASSWORD_ARGON2_P_COST", "1")
+                .parse()
+                .expect("invalid password_argon2_p_cost"),
             // 60 * 24 * 30
             auth_expiration_seconds: env_or("AUTH_EXPIRATION_SECONDS", "43200")
                 .parse()
                 .expect("invalid auth_expiration_seconds"),
+            // 60 * 60 * 24 * 30
+            auth_max_session_seconds: env_or("AUTH_MAX_SESSION_SECONDS", "2592000")
+                .parse()
+                .expect("invalid auth_max_session_seconds"),
+            auth_token_reap_interval_seconds: env_or("AUTH_TOKEN_REAP_INTERVAL_SECONDS", "3600")
+                .parse()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .expect("invalid auth_token_reap_interval_seconds, must be greater than 0"),
+            auth_token_reap_batch_size: env_or("AUTH_TOKEN_REAP_BATCH_SIZE", "1000")
+                .parse()
+                .expect("invalid auth_token_reap_batch_size"),
+            // 60 * 60 * 24 * 7
+            auth_token_retention_seconds: env_or("AUTH_TOKEN_RETENTION_SECONDS", "604800")
+                .parse()
+                .expect("invalid auth_token_retention_seconds"),
+            login_attempt_store: env_or("LOGIN_ATTEMPT_STORE", "memory"),
+            // 60 * 60 * 24
+            login_attempt_window_seconds: env_or("LOGIN_ATTEMPT_WINDOW_SECONDS", "86400")
+                .parse()
+                .expect("invalid login_attempt_window_seconds"),
+            login_max_failures_per_email: env_or("LOGIN_MAX_FAILURES_PER_EMAIL", "5")
+                .parse()
+                .expect("invalid login_max_failures_per_email"),
+            login_max_failures_per_ip: env_or("LOGIN_MAX_FAILURES_PER_IP", "50")
+                .parse()
+                .expect("invalid login_max_failures_per_ip"),
+            login_lockout_seconds: env_or("LOGIN_LOCKOUT_SECONDS", "60")
+                .parse()
+                .expect("invalid login_lockout_seconds"),
+            login_lockout_max_seconds: env_or("LOGIN_LOCKOUT_MAX_SECONDS", "3600")
+                .parse()
+                .expect("invalid login_lockout_max_seconds"),
+            // 60 * 30
+            password_reset_expiration_seconds: env_or("PASSWORD_RESET_EXPIRATION_SECONDS", "1800")
+                .parse()
+                .expect("invalid password_reset_expiration_seconds"),
+            // 60 * 60 * 24
+            email_verification_expiration_seconds: env_or(
+                "EMAIL_VERIFICATION_EXPIRATION_SECONDS",
+                "86400",
+            )
+            .parse()
+            .expect("invalid email_verification_expiration_seconds"),
+            // 60 * 60 * 24 * 7
+            creature_invite_expiration_seconds: env_or(
+                "CREATURE_INVITE_EXPIRATION_SECONDS",
+                "604800",
+            )
+            .parse()
+            .expect("invalid creature_invite_expiration_seconds"),
+            // 60 * 60 * 24 * 30
+            creature_restore_grace_seconds: env_or("CREATURE_RESTORE_GRACE_SECONDS", "2592000")
+                .parse()
+                .expect("invalid creature_restore_grace_seconds"),
+            // 60 * 5
+            login_challenge_expiration_seconds: env_or("LOGIN_CHALLENGE_EXPIRATION_SECONDS", "300")
+                .parse()
+                .expect("invalid login_challenge_expiration_seconds"),
+            totp_issuer: env_or("TOTP_ISSUER", "didpoop"),
+            mailer: env_or("MAILER", "log"),
+            mail_dir: env_or("MAIL_DIR", "mail"),
+            mail_from: env_or("MAIL_FROM", "noreply@didpoop.com"),
             encryption_key: env_or("ENCRYPTION_KEY", "01234567890123456789012345678901"),
             signing_key: env_or("SIGNING_KEY", "01234567890123456789012345678901"),
+            share_link_signing_key: env_or(
+                "SHARE_LINK_SIGNING_KEY",
+                "01234567890123456789012345678901",
+            ),
         }
     }
     pub fn initialize(&self) {
@@ -70,7 +206,19 @@ impl Config {
             real_host = ?CONFIG.real_host,
             db_max_connections = %CONFIG.db_max_connections,
             log_level = %CONFIG.log_level,
+            trusted_proxies = ?CONFIG.trusted_proxies,
+            password_hash_alg = %CONFIG.password_hash_alg,
             auth_expiration_seconds = %CONFIG.auth_expiration_seconds,
+            auth_max_session_seconds = %CONFIG.auth_max_session_seconds,
+            auth_token_reap_interval_seconds = %CONFIG.auth_token_reap_interval_seconds,
+            auth_token_reap_batch_size = %CONFIG.auth_token_reap_batch_size,
+            auth_token_retention_seconds = %CONFIG.auth_token_retention_seconds,
+            login_attempt_store = %CONFIG.login_attempt_store,
+            password_reset_expiration_seconds = %CONFIG.password_reset_expiration_seconds,
+            email_verification_expiration_seconds = %CONFIG.email_verification_expiration_seconds,
+            creature_invite_expiration_seconds = %CONFIG.creature_invite_expiration_seconds,
+            creature_restore_grace_seconds = %CONFIG.creature_restore_grace_seconds,
+            mailer = %CONFIG.mailer,
             "initialized config",
         );
     }
@@ -90,4 +238,16 @@ impl Config {
     pub fn get_login_url(&self) -> String {
         format!("{}/login", self.get_real_host())
     }
+    pub fn get_reset_password_url(&self, token: &str) -> String {
+        format!("{}/reset-password?token={}", self.get_real_host(), token)
+    }
+    pub fn get_verify_email_url(&self, token: &str) -> String {
+        format!("{}/verify-email?token={}", self.get_real_host(), token)
+    }
+    pub fn get_share_url(&self, token: &str) -> String {
+        format!("{}/share/{}", self.get_real_host(), token)
+    }
+    pub fn get_accept_invite_url(&self, token: &str) -> String {
+        format!("{}/invite?token={}", self.get_real_host(), token)
+    }
 }
@@ -35,6 +35,64 @@ pub fn derive_password_hash(pw: &[u8], salt: &[u8]) -> [u8; ring::digest::SHA512
     out
 }
 
+/// Password hashing algorithm and cost, stored alongside each user's hash
+/// so that the preferred parameters can change without invalidating old hashes
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "alg", rename_all = "kebab-case")]
+pub enum PasswordParams {
+    Pbkdf2Sha512 {
+        iterations: u32,
+    },
+    Argon2id {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+}
+
+/// The parameters new password hashes should be created with
+pub fn preferred_password_params() -> crate::Result<PasswordParams> {
+    match CONFIG.password_hash_alg.as_str() {
+        "pbkdf2-sha512" => Ok(PasswordParams::Pbkdf2Sha512 {
+            iterations: CONFIG.password_pbkdf2_iterations,
+        }),
+        "argon2id" => Ok(PasswordParams::Argon2id {
+            m_cost: CONFIG.password_argon2_m_cost,
+            t_cost: CONFIG.password_argon2_t_cost,
+            p_cost: CONFIG.password_argon2_p_cost,
+        }),
+        other => Err(format!("invalid password hash alg: {other}").into()),
+    }
+}
+
+/// Hash `pw` with the given algorithm and parameters
+pub fn hash_password(
+    pw: &[u8],
+    salt: &[u8],
+    params: &PasswordParams,
+) -> crate::Result<[u8; ring::digest::SHA512_OUTPUT_LEN]> {
+    let mut out = [0; ring::digest::SHA512_OUTPUT_LEN];
+    match *params {
+        PasswordParams::Pbkdf2Sha512 { iterations } => {
+            let iterations =
+                NonZeroU32::new(iterations).ok_or("pbkdf2 iterations must be non-zero")?;
+            pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA512, iterations, salt, pw, &mut out);
+        }
+        PasswordParams::Argon2id {
+            m_cost,
+            t_cost,
+            p_cost,
+        } => {
+            let params = argon2::Params::new(m_cost, t_cost, p_cost, Some(out.len()))
+                .map_err(|e| format!("invalid argon2 params: {e}"))?;
+            argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                .hash_password_into(pw, salt, &mut out)
+                .map_err(|e| format!("argon2 error: {e}"))?;
+        }
+    }
+    Ok(out)
+}
+
 pub fn hmac_sign(s: &str) -> String {
     hmac_sign_with_key(s, &crate::CONFIG.signing_key)
 }
@@ -42,7 +100,7 @@ pub fn hmac_sign_with_key(s: &str, key: &str) -> String {
     // using a 32 byte key
     let s_key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key.as_bytes());
     let tag = ring::hmac::sign(&s_key, s.as_bytes());
-    hex::encode(&tag)
+    hex::encode(tag)
 }
 
 pub fn hmac_verify(text: &str, sig: &str) -> bool {
@@ -61,6 +119,86 @@ pub fn hmac_verify_with_key(text: &str, sig: &str, key: &str) -> bool {
     ring::hmac::verify(&s_key, text.as_bytes(), &sig).is_ok()
 }
 
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// RFC 4648 base32 without padding, what authenticator apps expect for secrets
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for &b in bytes {
+        buf = (buf << 8) | b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buf >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buf << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+pub fn base32_decode(s: &str) -> crate::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for c in s.trim_end_matches('=').bytes() {
+        let v = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a == c.to_ascii_uppercase())
+            .ok_or("invalid base32")?;
+        buf = (buf << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buf >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+pub const TOTP_STEP_SECONDS: i64 = 30;
+pub const TOTP_DIGITS: usize = 6;
+
+/// RFC 6238 code for the given time step, using HMAC-SHA1 like every
+/// authenticator app does
+pub fn totp_code(secret: &[u8], step: i64) -> String {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let tag = ring::hmac::sign(&key, &step.to_be_bytes());
+    let h = tag.as_ref();
+    let offset = (h[h.len() - 1] & 0x0f) as usize;
+    let bin = u32::from_be_bytes([
+        h[offset] & 0x7f,
+        h[offset + 1],
+        h[offset + 2],
+        h[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        bin % 10u32.pow(TOTP_DIGITS as u32),
+        width = TOTP_DIGITS
+    )
+}
+
+/// Check `code` against the time steps around `unix_seconds`, allowing for one
+/// step of clock drift either way. Returns the step that matched.
+pub fn totp_verify(secret: &[u8], code: &str, unix_seconds: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS {
+        return None;
+    }
+    let step = unix_seconds / TOTP_STEP_SECONDS;
+    (step - 1..=step + 1).find(|s| {
+        ring::constant_time::verify_slices_are_equal(
+            totp_code(secret, *s).as_bytes(),
+            code.as_bytes(),
+        )
+        .is_ok()
+    })
+}
+
 /// ring requires an implementor of `NonceSequence`,
 /// which if a wrapping trait around `ring::aead::Nonce`.
 /// We have to make a wrapper that can pass ownership
@@ -182,3 +320,94 @@ pub fn decrypt_with_key(enc: &Enc, key: &str) -> crate::Result<String> {
     let s = String::from_utf8(bytes.to_owned()).map_err(|_| "error decrypting bytes")?;
     Ok(s)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4648 section 10
+    const BASE32_VECTORS: &[(&str, &str)] = &[
+        ("", ""),
+        ("f", "MY======"),
+        ("fo", "MZXQ===="),
+        ("foo", "MZXW6==="),
+        ("foob", "MZXW6YQ="),
+        ("fooba", "MZXW6YTB"),
+        ("foobar", "MZXW6YTBOI======"),
+    ];
+
+    #[test]
+    fn base32_encode_rfc4648() {
+        for (plain, encoded) in BASE32_VECTORS {
+            assert_eq!(
+                base32_encode(plain.as_bytes()),
+                encoded.trim_end_matches('=')
+            );
+        }
+    }
+
+    #[test]
+    fn base32_decode_rfc4648() {
+        for (plain, encoded) in BASE32_VECTORS {
+            assert_eq!(base32_decode(encoded).unwrap(), plain.as_bytes());
+            assert_eq!(
+                base32_decode(encoded.trim_end_matches('=')).unwrap(),
+                plain.as_bytes()
+            );
+            assert_eq!(
+                base32_decode(&encoded.to_lowercase()).unwrap(),
+                plain.as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn base32_decode_rejects_invalid() {
+        assert!(base32_decode("MZ1W").is_err());
+        assert!(base32_decode("MZ=W").is_err());
+        assert!(base32_decode("MZXW 6").is_err());
+    }
+
+    // RFC 6238 appendix B, SHA1, truncated from 8 to 6 digits
+    const TOTP_SECRET: &[u8] = b"12345678901234567890";
+    const TOTP_VECTORS: &[(i64, &str)] = &[
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+
+    #[test]
+    fn totp_code_rfc6238() {
+        for (t, code) in TOTP_VECTORS {
+            assert_eq!(totp_code(TOTP_SECRET, t / TOTP_STEP_SECONDS), *code);
+        }
+    }
+
+    #[test]
+    fn totp_verify_allows_one_step_of_drift() {
+        let t = 1111111109;
+        let step = t / TOTP_STEP_SECONDS;
+        for drift in -1..=1 {
+            let code = totp_code(TOTP_SECRET, step + drift);
+            assert_eq!(totp_verify(TOTP_SECRET, &code, t), Some(step + drift));
+        }
+        for drift in [-2, 2] {
+            let code = totp_code(TOTP_SECRET, step + drift);
+            assert_eq!(totp_verify(TOTP_SECRET, &code, t), None);
+        }
+    }
+
+    #[test]
+    fn totp_verify_rejects_malformed_codes() {
+        assert_eq!(
+            totp_verify(TOTP_SECRET, " 081804 ", 1111111109),
+            Some(37037036)
+        );
+        assert_eq!(totp_verify(TOTP_SECRET, "81804", 1111111109), None);
+        assert_eq!(totp_verify(TOTP_SECRET, "07081804", 1111111109), None);
+        assert_eq!(totp_verify(TOTP_SECRET, "", 1111111109), None);
+    }
+}
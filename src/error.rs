@@ -15,21 +15,19 @@ pub enum AppError {
     #[error("unauthorized")]
     Unauthorized(String),
 
-    #[allow(unused)]
     #[error("forbidden")]
     Forbidden(String),
 
     #[error("bad request")]
     BadRequest(String),
 
+    /// Too many failed attempts, retry after the given number of seconds
+    #[error("too many attempts")]
+    LockedOut(i64),
+
     #[error("hex error")]
     Hex(#[from] hex::FromHexError),
 }
-impl AppError {
-    pub fn is_db_not_found(&self) -> bool {
-        matches!(*self, Self::DBNotFound(_))
-    }
-}
 impl From<&str> for AppError {
     fn from(s: &str) -> AppError {
         AppError::E(s.to_string())
@@ -69,6 +67,11 @@ impl ErrorExtensions for AppError {
                 e.set("code", 400);
                 e.set("error", s.clone());
             }
+            AppError::LockedOut(retry_after) => {
+                e.set("code", 429);
+                e.set("error", "too many failed attempts, try again later");
+                e.set("retryAfter", *retry_after);
+            }
             AppError::Hex(_) => e.set("code", 500),
         })
     }
@@ -0,0 +1,224 @@
+/*!
+Validation against the subset of JSON schema used for event attributes.
+
+Supported keywords: `type` (object, array, string, integer, number,
+boolean, null), `properties`, `required`, `additionalProperties` (as a
+boolean), `items`, `enum`, `minimum`, `maximum`, `minLength` and
+`maxLength`. Anything else is ignored.
+*/
+use serde_json::Value;
+
+/// Check `value` against `schema`, returning a description of the first
+/// problem found
+pub fn validate(schema: &Value, value: &Value) -> Result<(), String> {
+    validate_at(schema, value, "attrs")
+}
+
+fn type_matches(ty: &str, value: &Value) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        // `true` and `{}` accept anything, `false` nothing
+        Value::Bool(true) => return Ok(()),
+        _ => return Err(format!("{path} is not allowed")),
+    };
+
+    if let Some(ty) = schema.get("type").and_then(Value::as_str) {
+        if !type_matches(ty, value) {
+            return Err(format!("{path} must be of type {ty}"));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed = allowed
+                .iter()
+                .map(Value::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(format!("{path} must be one of {allowed}"));
+        }
+    }
+
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if n < min {
+                return Err(format!("{path} must be at least {min}"));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if n > max {
+                return Err(format!("{path} must be at most {max}"));
+            }
+        }
+    }
+
+    if let Some(s) = value.as_str() {
+        let len = s.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+            if len < min {
+                return Err(format!("{path} must be at least {min} characters"));
+            }
+        }
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+            if len > max {
+                return Err(format!("{path} must be at most {max} characters"));
+            }
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                validate_at(item_schema, item, &format!("{path}[{i}]"))?;
+            }
+        }
+    }
+
+    if let Some(obj) = value.as_object() {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !obj.contains_key(key) {
+                    return Err(format!("{path}.{key} is required"));
+                }
+            }
+        }
+        let additional = schema
+            .get("additionalProperties")
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+        for (key, v) in obj {
+            match properties.and_then(|p| p.get(key)) {
+                Some(prop_schema) => validate_at(prop_schema, v, &format!("{path}.{key}"))?,
+                None if !additional => return Err(format!("{path}.{key} is not allowed")),
+                None => (),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn type_mismatch() {
+        let schema = json!({"type": "integer"});
+        assert!(validate(&schema, &json!(3)).is_ok());
+        assert_eq!(
+            validate(&schema, &json!(3.5)).unwrap_err(),
+            "attrs must be of type integer"
+        );
+        assert!(validate(&json!({"type": "number"}), &json!(3)).is_ok());
+        assert!(validate(&json!({"type": "string"}), &json!(null)).is_err());
+        assert!(validate(&json!({"type": "null"}), &json!(null)).is_ok());
+        assert!(validate(&json!({"type": "object"}), &json!([])).is_err());
+    }
+
+    #[test]
+    fn boolean_schemas() {
+        assert!(validate(&json!(true), &json!({"a": 1})).is_ok());
+        assert!(validate(&json!({}), &json!("anything")).is_ok());
+        assert_eq!(
+            validate(&json!(false), &json!(1)).unwrap_err(),
+            "attrs is not allowed"
+        );
+    }
+
+    #[test]
+    fn required_fields() {
+        let schema = json!({"type": "object", "required": ["amount"]});
+        assert!(validate(&schema, &json!({"amount": 1})).is_ok());
+        assert_eq!(
+            validate(&schema, &json!({})).unwrap_err(),
+            "attrs.amount is required"
+        );
+    }
+
+    #[test]
+    fn enum_values() {
+        let schema = json!({"enum": ["wet", "dry"]});
+        assert!(validate(&schema, &json!("dry")).is_ok());
+        assert_eq!(
+            validate(&schema, &json!("damp")).unwrap_err(),
+            r#"attrs must be one of "wet", "dry""#
+        );
+    }
+
+    #[test]
+    fn numeric_bounds() {
+        let schema = json!({"type": "number", "minimum": 0, "maximum": 10});
+        assert!(validate(&schema, &json!(0)).is_ok());
+        assert!(validate(&schema, &json!(10)).is_ok());
+        assert_eq!(
+            validate(&schema, &json!(-0.5)).unwrap_err(),
+            "attrs must be at least 0"
+        );
+        assert_eq!(
+            validate(&schema, &json!(11)).unwrap_err(),
+            "attrs must be at most 10"
+        );
+    }
+
+    #[test]
+    fn string_lengths() {
+        let schema = json!({"type": "string", "minLength": 2, "maxLength": 3});
+        assert!(validate(&schema, &json!("ab")).is_ok());
+        // counted in characters, not bytes
+        assert!(validate(&schema, &json!("äöü")).is_ok());
+        assert!(validate(&schema, &json!("a")).is_err());
+        assert!(validate(&schema, &json!("abcd")).is_err());
+    }
+
+    #[test]
+    fn nested_objects_and_arrays() {
+        let schema = json!({
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "food": {
+                    "type": "object",
+                    "required": ["name"],
+                    "properties": {
+                        "name": {"type": "string"},
+                        "grams": {"type": "number", "minimum": 0},
+                    },
+                },
+                "tags": {"type": "array", "items": {"type": "string"}},
+            },
+        });
+        let ok = json!({"food": {"name": "kibble", "grams": 40}, "tags": ["am"]});
+        assert!(validate(&schema, &ok).is_ok());
+        assert_eq!(
+            validate(&schema, &json!({"food": {"grams": 40}})).unwrap_err(),
+            "attrs.food.name is required"
+        );
+        assert_eq!(
+            validate(&schema, &json!({"food": {"name": "kibble", "grams": -1}})).unwrap_err(),
+            "attrs.food.grams must be at least 0"
+        );
+        assert_eq!(
+            validate(&schema, &json!({"tags": ["am", 2]})).unwrap_err(),
+            "attrs.tags[1] must be of type string"
+        );
+        assert_eq!(
+            validate(&schema, &json!({"extra": 1})).unwrap_err(),
+            "attrs.extra is not allowed"
+        );
+    }
+}
@@ -1,7 +1,11 @@
-use crate::models::{CreatureRelation, Poop, User};
+use crate::models::{
+    ApiToken, AuthToken, CreatureAccessKind, CreatureInvite, CreatureMember, CreatureRelation,
+    Event, Poop, PoopFilter, ShareLink, User, Weight,
+};
 use crate::AppError;
 use async_graphql::dataloader::{DataLoader, HashMapCache};
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool, Row};
 use std::collections::HashMap;
 
 pub struct PgLoader {
@@ -63,6 +67,8 @@ impl async_graphql::dataloader::Loader<CreatureUserId> for PgLoader {
                 inner join poop.creature_access ca on ca.creature_id = c.id
             where c.deleted is false
                 and ca.deleted is false
+                and (ca.starts is null or ca.starts <= now())
+                and (ca.expires is null or ca.expires > now())
                 and (
                     ca.user_id in (select * from unnest($1))
                     or ca.creature_id in (select * from unnest($2))
@@ -103,6 +109,8 @@ impl async_graphql::dataloader::Loader<CreaturesForUserId> for PgLoader {
                 inner join poop.creature_access ca on ca.creature_id = c.id
             where ca.user_id in (select * from unnest($1))
                 and ca.deleted is false
+                and (ca.starts is null or ca.starts <= now())
+                and (ca.expires is null or ca.expires > now())
                 and c.deleted is false
         "##;
         let keys = keys.iter().map(|c| c.0).collect::<Vec<_>>();
@@ -125,8 +133,90 @@ impl async_graphql::dataloader::Loader<CreaturesForUserId> for PgLoader {
     }
 }
 
+/// Columns of the `jsonb_to_recordset` holding each key's `PoopFilter`
+const POOP_FILTER_COLUMNS: &str = r##"
+    occurred_from timestamptz,
+    occurred_to timestamptz,
+    creator_id bigint,
+    bristol_min int,
+    bristol_max int,
+    colors text[],
+    sizes text[],
+    blood boolean,
+    mucus boolean
+"##;
+
+/// Restricts poops `p` to the filter in recordset row `k`
+const POOP_FILTER_CONDITIONS: &str = r##"
+    and (k.occurred_from is null or p.occurred_at >= k.occurred_from)
+    and (k.occurred_to is null or p.occurred_at < k.occurred_to)
+    and (k.creator_id is null or p.creator_id = k.creator_id)
+    and (k.bristol_min is null or p.bristol >= k.bristol_min)
+    and (k.bristol_max is null or p.bristol <= k.bristol_max)
+    and (k.colors is null or p.color = any(k.colors))
+    and (k.sizes is null or p.size = any(k.sizes))
+    and (k.blood is null or p.blood = k.blood)
+    and (k.mucus is null or p.mucus = k.mucus)
+"##;
+
+/// A recordset row for the key at `idx`, `fields` are added to its filter
+fn poop_filter_row(
+    idx: usize,
+    filter: &PoopFilter,
+    fields: serde_json::Value,
+) -> serde_json::Value {
+    let colors = filter
+        .colors
+        .as_ref()
+        .map(|cs| cs.iter().map(|c| c.as_str()).collect::<Vec<_>>());
+    let sizes = filter
+        .sizes
+        .as_ref()
+        .map(|ss| ss.iter().map(|s| s.as_str()).collect::<Vec<_>>());
+    let mut row = serde_json::json!({
+        "idx": idx,
+        "occurred_from": filter.from.map(|t| t.to_rfc3339()),
+        "occurred_to": filter.to.map(|t| t.to_rfc3339()),
+        // validated by `PoopFilter::validate`
+        "creator_id": filter.creator_id,
+        "bristol_min": filter.bristol_min,
+        "bristol_max": filter.bristol_max,
+        "colors": colors,
+        "sizes": sizes,
+        "blood": filter.blood,
+        "mucus": filter.mucus,
+    });
+    if let (Some(row), serde_json::Value::Object(fields)) = (row.as_object_mut(), fields) {
+        row.extend(fields);
+    }
+    row
+}
+
+/// Rows grouped under the key at each row's `page_idx`
+fn group_page_rows<K, T>(
+    keys: &[K],
+    rows: Vec<sqlx::postgres::PgRow>,
+) -> std::result::Result<HashMap<K, Vec<T>>, AppError>
+where
+    K: Clone + Eq + std::hash::Hash,
+    T: for<'r> FromRow<'r, sqlx::postgres::PgRow>,
+{
+    let mut res = keys
+        .iter()
+        .map(|k| (k.clone(), Vec::new()))
+        .collect::<HashMap<_, _>>();
+    for row in rows {
+        let idx: i32 = row.try_get("page_idx")?;
+        let item = T::from_row(&row)?;
+        if let Some(items) = res.get_mut(&keys[idx as usize]) {
+            items.push(item);
+        }
+    }
+    Ok(res)
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
-pub struct PoopsForCreatureId(pub i64);
+pub struct PoopsForCreatureId(pub i64, pub PoopFilter);
 
 #[async_trait::async_trait]
 impl async_graphql::dataloader::Loader<PoopsForCreatureId> for PgLoader {
@@ -138,28 +228,547 @@ impl async_graphql::dataloader::Loader<PoopsForCreatureId> for PgLoader {
         keys: &[PoopsForCreatureId],
     ) -> std::result::Result<HashMap<PoopsForCreatureId, Self::Value>, Self::Error> {
         tracing::info!("loading {} poops for creatures", keys.len());
+        let query = format!(
+            r##"
+            select k.idx as page_idx, p.* from jsonb_to_recordset($1) as k(
+                idx int,
+                creature_id bigint,
+                {POOP_FILTER_COLUMNS}
+            )
+            inner join poop.poops p on p.creature_id = k.creature_id
+            where p.deleted is false
+                {POOP_FILTER_CONDITIONS}
+                order by p.occurred_at desc, p.id desc
+            "##
+        );
+        let filters = keys
+            .iter()
+            .enumerate()
+            .map(|(i, k)| poop_filter_row(i, &k.1, serde_json::json!({ "creature_id": k.0 })))
+            .collect::<Vec<_>>();
+        let rows = sqlx::query(&query)
+            .bind(sqlx::types::Json(filters))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} poops for creatures", rows.len());
+        Ok(group_page_rows(keys, rows)?)
+    }
+}
+
+/// One page of a creature's poops matching `filter`, ordered newest
+/// first. Pages run from just past `after` when going forward, or back
+/// from just before `before` when `backward`, and hold at most `limit`
+/// poops.
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct PoopsPage {
+    pub creature_id: i64,
+    pub filter: PoopFilter,
+    pub after: Option<i64>,
+    pub before: Option<i64>,
+    pub limit: i64,
+    pub backward: bool,
+}
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<PoopsPage> for PgLoader {
+    type Value = Vec<Poop>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[PoopsPage],
+    ) -> std::result::Result<HashMap<PoopsPage, Self::Value>, Self::Error> {
+        tracing::info!("loading {} poop pages", keys.len());
+        // cursors are poop ids, pages are positioned by the cursor poop's
+        // (occurred_at, id) so backdated poops still sort by occurred_at
+        let query = format!(
+            r##"
+            select k.idx as page_idx, p.* from jsonb_to_recordset($1) as k(
+                idx int,
+                creature_id bigint,
+                after_id bigint,
+                before_id bigint,
+                lim bigint,
+                backward boolean,
+                {POOP_FILTER_COLUMNS}
+            )
+            cross join lateral (
+                select p.* from poop.poops p
+                where p.creature_id = k.creature_id
+                    and p.deleted is false
+                    and (k.after_id is null or (p.occurred_at, p.id) <
+                        (select a.occurred_at, a.id from poop.poops a where a.id = k.after_id))
+                    and (k.before_id is null or (p.occurred_at, p.id) >
+                        (select b.occurred_at, b.id from poop.poops b where b.id = k.before_id))
+                    {POOP_FILTER_CONDITIONS}
+                    order by
+                        case when k.backward then p.occurred_at end asc,
+                        case when k.backward then p.id end asc,
+                        case when not k.backward then p.occurred_at end desc,
+                        case when not k.backward then p.id end desc
+                    limit k.lim
+            ) p
+            "##
+        );
+        let pages = keys
+            .iter()
+            .enumerate()
+            .map(|(i, k)| {
+                poop_filter_row(
+                    i,
+                    &k.filter,
+                    serde_json::json!({
+                        "creature_id": k.creature_id,
+                        "after_id": k.after,
+                        "before_id": k.before,
+                        "lim": k.limit,
+                        "backward": k.backward,
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+        let rows = sqlx::query(&query)
+            .bind(sqlx::types::Json(pages))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} poops for pages", rows.len());
+        Ok(group_page_rows(keys, rows)?)
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct SessionsForUserId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<SessionsForUserId> for PgLoader {
+    type Value = Vec<AuthToken>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[SessionsForUserId],
+    ) -> std::result::Result<HashMap<SessionsForUserId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} sessions for users", keys.len());
         let query = r##"
-            select p.* from poop.poops p
-            where p.creature_id in (select * from unnest($1))
-                and p.deleted is false
-                order by p.created desc
+            select at.* from poop.auth_tokens at
+            where at.user_id in (select * from unnest($1))
+                and at.deleted is false
+                and at.expires > now()
+                order by at.last_seen desc
         "##;
         let keys = keys.iter().map(|c| c.0).collect::<Vec<_>>();
-        let res: Vec<Poop> = sqlx::query_as(query)
+        let res: Vec<AuthToken> = sqlx::query_as(query)
             .bind(&keys)
             .fetch_all(&self.pool)
             .await
             .map_err(AppError::from)?;
-        tracing::info!("loaded {} poops for creatures", res.len());
-        let res = res.into_iter().fold(HashMap::new(), |mut acc, p| {
+        tracing::info!("loaded {} sessions for users", res.len());
+        let res = res.into_iter().fold(HashMap::new(), |mut acc, t| {
             {
                 let e = acc
-                    .entry(PoopsForCreatureId(p.creature_id))
+                    .entry(SessionsForUserId(t.user_id))
                     .or_insert_with(Vec::new);
-                e.push(p);
+                e.push(t);
             }
             acc
         });
         Ok(res)
     }
 }
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct ApiTokensForUserId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<ApiTokensForUserId> for PgLoader {
+    type Value = Vec<ApiToken>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[ApiTokensForUserId],
+    ) -> std::result::Result<HashMap<ApiTokensForUserId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} api tokens for users", keys.len());
+        let query = r##"
+            select t.* from poop.api_tokens t
+            where t.user_id in (select * from unnest($1))
+                and t.deleted is false
+                order by t.created desc
+        "##;
+        let keys = keys.iter().map(|c| c.0).collect::<Vec<_>>();
+        let res: Vec<ApiToken> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} api tokens for users", res.len());
+        let res = res.into_iter().fold(HashMap::new(), |mut acc, t| {
+            {
+                let e = acc
+                    .entry(ApiTokensForUserId(t.user_id))
+                    .or_insert_with(Vec::new);
+                e.push(t);
+            }
+            acc
+        });
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct PendingInvitesForUserId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<PendingInvitesForUserId> for PgLoader {
+    type Value = Vec<CreatureInvite>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[PendingInvitesForUserId],
+    ) -> std::result::Result<HashMap<PendingInvitesForUserId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} pending invites for users", keys.len());
+        let query = r##"
+            select i.*, c.name as creature_name from poop.creature_invites i
+                inner join poop.creatures c on c.id = i.creature_id
+            where i.user_id in (select * from unnest($1))
+                and i.deleted is false
+                and i.expires > now()
+                and c.deleted is false
+                order by i.created desc
+        "##;
+        let keys = keys.iter().map(|c| c.0).collect::<Vec<_>>();
+        let res: Vec<CreatureInvite> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} pending invites for users", res.len());
+        let res = res.into_iter().fold(HashMap::new(), |mut acc, i| {
+            {
+                let e = acc
+                    .entry(PendingInvitesForUserId(i.user_id))
+                    .or_insert_with(Vec::new);
+                e.push(i);
+            }
+            acc
+        });
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct MembersForCreatureId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<MembersForCreatureId> for PgLoader {
+    type Value = Vec<CreatureMember>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[MembersForCreatureId],
+    ) -> std::result::Result<HashMap<MembersForCreatureId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} members for creatures", keys.len());
+        let query = r##"
+            select ca.creature_id, ca.user_id, u.name, ca.kind, ca.starts, ca.expires, ca.created
+                from poop.creature_access ca
+                inner join poop.users u on u.id = ca.user_id
+            where ca.creature_id in (select * from unnest($1))
+                and ca.deleted is false
+                and (ca.starts is null or ca.starts <= now())
+                and (ca.expires is null or ca.expires > now())
+                and u.deleted is false
+                order by ca.created
+        "##;
+        let keys = keys.iter().map(|c| c.0).collect::<Vec<_>>();
+        let res: Vec<CreatureMember> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} members for creatures", res.len());
+        let res = res.into_iter().fold(HashMap::new(), |mut acc, m| {
+            {
+                let e = acc
+                    .entry(MembersForCreatureId(m.creature_id))
+                    .or_insert_with(Vec::new);
+                e.push(m);
+            }
+            acc
+        });
+        Ok(res)
+    }
+}
+
+/// A user's access to a creature, keyed by (creature id, user id)
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct CreatureAccess(pub i64, pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<CreatureAccess> for PgLoader {
+    type Value = CreatureAccessKind;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[CreatureAccess],
+    ) -> std::result::Result<HashMap<CreatureAccess, Self::Value>, Self::Error> {
+        tracing::info!("loading {} creature access", keys.len());
+        let query = r##"
+            select ca.creature_id, ca.user_id, ca.kind from poop.creature_access ca
+                inner join poop.creatures c on c.id = ca.creature_id
+                inner join unnest($1, $2) as k(creature_id, user_id)
+                    on k.creature_id = ca.creature_id and k.user_id = ca.user_id
+            where c.deleted is false
+                and ca.deleted is false
+                and (ca.starts is null or ca.starts <= now())
+                and (ca.expires is null or ca.expires > now())
+        "##;
+        #[derive(sqlx::FromRow)]
+        struct Access {
+            creature_id: i64,
+            user_id: i64,
+            kind: String,
+        }
+        let c_ids = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let u_ids = keys.iter().map(|k| k.1).collect::<Vec<_>>();
+        let res: Vec<Access> = sqlx::query_as(query)
+            .bind(&c_ids)
+            .bind(&u_ids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} creature access", res.len());
+        let res = res.into_iter().fold(HashMap::new(), |mut acc, a| {
+            acc.insert(
+                CreatureAccess(a.creature_id, a.user_id),
+                CreatureAccessKind::from_db(&a.kind),
+            );
+            acc
+        });
+        Ok(res)
+    }
+}
+
+/// Temporary access to a creature, whether upcoming, active or expired
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct GrantsForCreatureId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<GrantsForCreatureId> for PgLoader {
+    type Value = Vec<CreatureMember>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[GrantsForCreatureId],
+    ) -> std::result::Result<HashMap<GrantsForCreatureId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} grants for creatures", keys.len());
+        let query = r##"
+            select ca.creature_id, ca.user_id, u.name, ca.kind, ca.starts, ca.expires, ca.created
+                from poop.creature_access ca
+                inner join poop.users u on u.id = ca.user_id
+            where ca.creature_id in (select * from unnest($1))
+                and ca.deleted is false
+                and ca.expires is not null
+                and u.deleted is false
+                order by ca.expires desc
+        "##;
+        let keys = keys.iter().map(|c| c.0).collect::<Vec<_>>();
+        let res: Vec<CreatureMember> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} grants for creatures", res.len());
+        let res = res.into_iter().fold(HashMap::new(), |mut acc, m| {
+            {
+                let e = acc
+                    .entry(GrantsForCreatureId(m.creature_id))
+                    .or_insert_with(Vec::new);
+                e.push(m);
+            }
+            acc
+        });
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct ShareLinksForCreatureId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<ShareLinksForCreatureId> for PgLoader {
+    type Value = Vec<ShareLink>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[ShareLinksForCreatureId],
+    ) -> std::result::Result<HashMap<ShareLinksForCreatureId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} share links for creatures", keys.len());
+        let query = r##"
+            select sl.* from poop.share_links sl
+            where sl.creature_id in (select * from unnest($1))
+                and sl.deleted is false
+                order by sl.created desc
+        "##;
+        let keys = keys.iter().map(|c| c.0).collect::<Vec<_>>();
+        let res: Vec<ShareLink> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} share links for creatures", res.len());
+        let res = res.into_iter().fold(HashMap::new(), |mut acc, l| {
+            {
+                let e = acc
+                    .entry(ShareLinksForCreatureId(l.creature_id))
+                    .or_insert_with(Vec::new);
+                e.push(l);
+            }
+            acc
+        });
+        Ok(res)
+    }
+}
+
+/// One page of a creature's events, newest first, optionally only of
+/// `kinds`. Pages start just past the `after` event and hold at most
+/// `limit` events.
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct EventsPage {
+    pub creature_id: i64,
+    pub kinds: Option<Vec<String>>,
+    pub after: Option<i64>,
+    pub limit: i64,
+}
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<EventsPage> for PgLoader {
+    type Value = Vec<Event>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[EventsPage],
+    ) -> std::result::Result<HashMap<EventsPage, Self::Value>, Self::Error> {
+        tracing::info!("loading {} event pages", keys.len());
+        let query = r##"
+            select k.idx as page_idx, e.* from jsonb_to_recordset($1) as k(
+                idx int,
+                creature_id bigint,
+                kinds text[],
+                after_id bigint,
+                lim int
+            )
+            cross join lateral (
+                select e.* from poop.events e
+                where e.creature_id = k.creature_id
+                    and e.deleted is false
+                    and (k.kinds is null or e.kind = any(k.kinds))
+                    and (k.after_id is null or (e.occurred_at, e.id) <
+                        (select a.occurred_at, a.id from poop.events a where a.id = k.after_id))
+                    order by e.occurred_at desc, e.id desc
+                    limit k.lim
+            ) e
+            order by k.idx, e.occurred_at desc, e.id desc
+        "##;
+        let pages = keys
+            .iter()
+            .enumerate()
+            .map(|(i, k)| {
+                serde_json::json!({
+                    "idx": i,
+                    "creature_id": k.creature_id,
+                    "kinds": k.kinds,
+                    "after_id": k.after,
+                    "lim": k.limit,
+                })
+            })
+            .collect::<Vec<_>>();
+        let rows = sqlx::query(query)
+            .bind(sqlx::types::Json(pages))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} events for pages", rows.len());
+        Ok(group_page_rows(keys, rows)?)
+    }
+}
+
+/// Weights for a creature measured in `[from, to)`, with the growth since
+/// each one's previous measurement
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct WeightsForCreatureId(
+    pub i64,
+    pub Option<DateTime<Utc>>,
+    pub Option<DateTime<Utc>>,
+);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<WeightsForCreatureId> for PgLoader {
+    type Value = Vec<Weight>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[WeightsForCreatureId],
+    ) -> std::result::Result<HashMap<WeightsForCreatureId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} weights for creatures", keys.len());
+        // growth is computed before the range is applied, starting from the
+        // last weight before `from`, so the first weight in range still has one
+        let query = r##"
+            select k.idx as page_idx, w.* from jsonb_to_recordset($1) as k(
+                idx int,
+                creature_id bigint,
+                measured_from timestamptz,
+                measured_to timestamptz
+            )
+            cross join lateral (
+                select * from (
+                    select w.*,
+                        ((w.grams - lag(w.grams) over win)
+                            / nullif(extract(epoch from w.measured_at - lag(w.measured_at) over win), 0)
+                            * 86400)::double precision as growth_grams_per_day
+                    from poop.weights w
+                    where w.creature_id = k.creature_id
+                        and w.deleted is false
+                        and (k.measured_to is null or w.measured_at < k.measured_to)
+                        and (k.measured_from is null or w.measured_at >= coalesce(
+                            (select max(p.measured_at) from poop.weights p
+                                where p.creature_id = k.creature_id
+                                    and p.deleted is false
+                                    and p.measured_at < k.measured_from),
+                            k.measured_from
+                        ))
+                    window win as (order by w.measured_at, w.id)
+                ) w
+                where k.measured_from is null or w.measured_at >= k.measured_from
+            ) w
+            order by k.idx, w.measured_at, w.id
+        "##;
+        let ranges = keys
+            .iter()
+            .enumerate()
+            .map(|(i, k)| {
+                serde_json::json!({
+                    "idx": i,
+                    "creature_id": k.0,
+                    "measured_from": k.1.map(|t| t.to_rfc3339()),
+                    "measured_to": k.2.map(|t| t.to_rfc3339()),
+                })
+            })
+            .collect::<Vec<_>>();
+        let rows = sqlx::query(query)
+            .bind(sqlx::types::Json(ranges))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} weights for creatures", rows.len());
+        Ok(group_page_rows(keys, rows)?)
+    }
+}
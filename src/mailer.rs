@@ -0,0 +1,80 @@
+/*!
+Outgoing email
+*/
+use crate::{Result, CONFIG};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+impl Email {
+    fn format(&self) -> String {
+        format!(
+            "From: {from}\nTo: {to}\nSubject: {subject}\n\n{body}\n",
+            from = &CONFIG.mail_from,
+            to = &self.to,
+            subject = &self.subject,
+            body = &self.body,
+        )
+    }
+}
+
+#[async_trait::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<()>;
+}
+
+pub type AppMailer = Arc<dyn Mailer>;
+
+/// Build the mailer selected by `CONFIG.mailer`
+pub fn from_config() -> Result<AppMailer> {
+    match CONFIG.mailer.as_str() {
+        "log" => Ok(Arc::new(LogMailer)),
+        "file" => Ok(Arc::new(FileMailer::new(&CONFIG.mail_dir))),
+        other => Err(format!("invalid mailer: {other}").into()),
+    }
+}
+
+/// Writes emails to the log instead of sending them
+pub struct LogMailer;
+
+#[async_trait::async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> Result<()> {
+        tracing::info!(to = %email.to, subject = %email.subject, "sending email:\n{}", email.format());
+        Ok(())
+    }
+}
+
+/// Writes each email to its own file under `dir`
+pub struct FileMailer {
+    dir: PathBuf,
+}
+impl FileMailer {
+    pub fn new(dir: &str) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("error creating mail dir: {e}"))?;
+        let name = format!(
+            "{}-{}.eml",
+            chrono::Utc::now().timestamp_millis(),
+            hex::encode(crate::crypto::rand_bytes(4)?)
+        );
+        let path = self.dir.join(name);
+        tokio::fs::write(&path, email.format())
+            .await
+            .map_err(|e| format!("error writing mail file: {e}"))?;
+        tracing::info!(to = %email.to, subject = %email.subject, path = %path.display(), "wrote email");
+        Ok(())
+    }
+}
@@ -1,21 +1,28 @@
 use async_graphql::{dataloader::HashMapCache, EmptySubscription};
 use async_graphql_warp::GraphQLResponse;
+use chrono::{Duration, Utc};
 use sqlx::PgPool;
 use std::convert::Infallible;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use warp::{hyper::Method, Filter};
 
 mod config;
 mod crypto;
 mod error;
+mod json_schema;
 mod loaders;
+mod mailer;
 mod models;
+mod reaper;
 mod schema;
+mod share;
+mod stats;
+mod throttle;
 
 use error::{AppError, Result};
 use loaders::PgLoader;
-use models::User;
-use schema::{MutationRoot, QueryRoot, Schema};
+use models::{ApiToken, AuthToken, RequestMeta, User};
+use schema::{format_set_cookie, MutationRoot, QueryRoot, Schema};
 
 lazy_static::lazy_static! {
     pub static ref CONFIG: config::Config = config::Config::load();
@@ -29,6 +36,146 @@ async fn main() {
     }
 }
 
+/// The client's ip. `x-forwarded-for` is only believed when the request
+/// came from a trusted proxy, and then only up to the right-most hop that
+/// isn't one of ours since anything left of that is client supplied.
+fn client_ip(
+    remote: Option<IpAddr>,
+    forwarded: Option<&str>,
+    trusted: &[IpAddr],
+) -> Option<IpAddr> {
+    let remote = remote?;
+    if !trusted.contains(&remote) {
+        return Some(remote);
+    }
+    let hops = match forwarded {
+        Some(f) => f.split(',').map(str::trim).collect::<Vec<_>>(),
+        None => return Some(remote),
+    };
+    let mut ip = remote;
+    for hop in hops.into_iter().rev() {
+        match hop.parse::<IpAddr>() {
+            Ok(hop) => {
+                ip = hop;
+                if !trusted.contains(&hop) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    Some(ip)
+}
+
+/// Pull the user agent and client ip off the request
+fn request_meta() -> impl Filter<Extract = (RequestMeta,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("user-agent")
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .and(warp::addr::remote())
+        .map(
+            |user_agent: Option<String>, forwarded: Option<String>, remote: Option<SocketAddr>| {
+                let ip = client_ip(
+                    remote.map(|r| r.ip()),
+                    forwarded.as_deref(),
+                    &CONFIG.trusted_proxies,
+                )
+                .map(|ip| ip.to_string());
+                RequestMeta { user_agent, ip }
+            },
+        )
+}
+
+/// How stale `last_seen` can get before a request bumps it
+const LAST_SEEN_RESOLUTION_SECONDS: i64 = 60;
+
+/// Look up the non-expired auth token matching `hash` along with its user,
+/// bumping the token's `last_seen` when it's out of date
+async fn find_session(pool: &PgPool, hash: &str) -> Result<Option<(User, AuthToken)>> {
+    let token: Option<AuthToken> = sqlx::query_as(
+        r##"
+        select * from poop.auth_tokens
+        where hash = $1
+            and deleted is false
+            and expires > now()"##,
+    )
+    .bind(hash)
+    .fetch_optional(pool)
+    .await?;
+    let mut token = match token {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+    if token.last_seen < Utc::now() - Duration::seconds(LAST_SEEN_RESOLUTION_SECONDS) {
+        sqlx::query("update poop.auth_tokens set last_seen = now() where id = $1")
+            .bind(token.id)
+            .execute(pool)
+            .await?;
+        token.last_seen = Utc::now();
+    }
+    let user: Option<User> =
+        sqlx::query_as("select * from poop.users where id = $1 and deleted is false")
+            .bind(token.user_id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(user.map(|u| (u, token)))
+}
+
+/// Look up the non-expired api token matching `hash` along with its user,
+/// bumping the token's `last_used`
+async fn find_api_token(pool: &PgPool, hash: &str) -> Result<Option<(User, ApiToken)>> {
+    let token: Option<ApiToken> = sqlx::query_as(
+        r##"
+        update poop.api_tokens set last_used = now()
+        where hash = $1
+            and deleted is false
+            and (expires is null or expires > now())
+        returning *"##,
+    )
+    .bind(hash)
+    .fetch_optional(pool)
+    .await?;
+    let token = match token {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+    let user: Option<User> =
+        sqlx::query_as("select * from poop.users where id = $1 and deleted is false")
+            .bind(token.user_id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(user.map(|u| (u, token)))
+}
+
+/// Slide the token's expiration forward once it's past half of its
+/// lifetime, capped at `auth_max_session_seconds` from when it was created.
+/// Returns the updated token when it was extended.
+async fn refresh_session(pool: &PgPool, token: &AuthToken) -> Result<Option<AuthToken>> {
+    let now = Utc::now();
+    let lifetime = Duration::seconds(CONFIG.auth_expiration_seconds as i64);
+    if token.expires - now > lifetime / 2 {
+        return Ok(None);
+    }
+    let max_expires = token.created + Duration::seconds(CONFIG.auth_max_session_seconds as i64);
+    let expires = (now + lifetime).min(max_expires);
+    if expires <= token.expires {
+        return Ok(None);
+    }
+    // the token may have been revoked since it was looked up
+    let token: Option<AuthToken> = sqlx::query_as(
+        r##"
+        update poop.auth_tokens set expires = $2
+        where id = $1
+            and deleted is false
+            and expires > now()
+        returning *"##,
+    )
+    .bind(token.id)
+    .bind(expires)
+    .fetch_optional(pool)
+    .await?;
+    Ok(token)
+}
+
 async fn run() -> Result<()> {
     dotenv::dotenv().ok();
 
@@ -36,6 +183,7 @@ async fn run() -> Result<()> {
     let filter = tracing_subscriber::filter::EnvFilter::new(&CONFIG.log_level);
     tracing_subscriber::fmt().with_env_filter(filter).init();
     let pool = sqlx::PgPool::connect(&CONFIG.db_url).await?;
+    reaper::spawn(pool.clone());
 
     let status = warp::path("status").and(warp::get()).map(move || {
         #[derive(serde::Serialize)]
@@ -56,8 +204,17 @@ async fn run() -> Result<()> {
 
     let index = warp::any().and(warp::path::end()).map(|| "hello");
 
+    let share_pool = pool.clone();
+    let share = warp::path!("share" / String)
+        .and(warp::get())
+        .and(warp::any().map(move || share_pool.clone()))
+        .and_then(share::page);
+
+    let mailer = mailer::from_config()?;
     let schema = async_graphql::Schema::build(QueryRoot, MutationRoot, EmptySubscription)
         .data(pool.clone())
+        .data(mailer)
+        .data(throttle::Throttle::from_config(pool.clone())?)
         .finish();
 
     let graphql_post = warp::path!("api" / "graphql")
@@ -65,31 +222,57 @@ async fn run() -> Result<()> {
         .and(warp::post())
         .map(move || pool.clone())
         .and(warp::filters::cookie::optional(&CONFIG.cookie_name))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(request_meta())
         .and(async_graphql_warp::graphql(schema.clone()))
         .and_then(
             |pool: PgPool,
              cookie: Option<String>,
+             authorization: Option<String>,
+             meta: RequestMeta,
              (schema, mut request): (Schema, async_graphql::Request)| async move {
-                if let Some(cookie) = cookie {
+                let mut refreshed_cookie = None;
+                let bearer = authorization
+                    .as_deref()
+                    .and_then(|a| a.strip_prefix("Bearer "))
+                    .map(str::trim);
+                if let Some(bearer) = bearer {
+                    let hash = crypto::hmac_sign(bearer);
+                    match find_api_token(&pool, &hash).await {
+                        Ok(Some((u, token))) => {
+                            tracing::info!(user = %u.email, user_id = %u.id, api_token_id = %token.id, "found api token user for request");
+                            request.data.insert(u);
+                            request.data.insert(token);
+                        }
+                        Ok(None) => (),
+                        Err(e) => tracing::error!(error = ?e, "error looking up api token"),
+                    }
+                } else if let Some(cookie) = cookie {
                     let hash = crypto::hmac_sign(&cookie);
-                    let u: Result<User> = sqlx::query_as(
-                        r##"
-                        select u.* from poop.users u
-                            inner join poop.auth_tokens at on u.id = at.user_id
-                        where at.hash = $1
-                            and at.deleted is false
-                            and at.expires > now()
-                            and u.deleted is false"##,
-                    )
-                    .bind(hash)
-                    .fetch_one(&pool)
-                    .await
-                    .map_err(AppError::from);
-                    if let Ok(u) = u {
-                        tracing::info!(user = %u.email, user_id = %u.id, "found user for request");
-                        request.data.insert(u);
+                    match find_session(&pool, &hash).await {
+                        Ok(Some((u, token))) => {
+                            tracing::info!(user = %u.email, user_id = %u.id, "found user for request");
+                            let token = match refresh_session(&pool, &token).await {
+                                Ok(Some(refreshed)) => {
+                                    tracing::info!(user_id = %u.id, expires = %refreshed.expires, "extended session");
+                                    let max_age = (refreshed.expires - Utc::now()).num_seconds();
+                                    refreshed_cookie = Some(format_set_cookie(&cookie, max_age));
+                                    refreshed
+                                }
+                                Ok(None) => token,
+                                Err(e) => {
+                                    tracing::error!(error = ?e, "error extending session");
+                                    token
+                                }
+                            };
+                            request.data.insert(u);
+                            request.data.insert(token);
+                        }
+                        Ok(None) => (),
+                        Err(e) => tracing::error!(error = ?e, "error looking up session"),
                     }
                 }
+                request.data.insert(meta);
                 let loader = async_graphql::dataloader::DataLoader::with_cache(
                     PgLoader::new(pool),
                     tokio::spawn,
@@ -97,7 +280,13 @@ async fn run() -> Result<()> {
                 );
                 request.data.insert(loader);
 
-                let resp = schema.execute(request).await;
+                let mut resp = schema.execute(request).await;
+                if let Some(cookie_str) = refreshed_cookie {
+                    // logins and logouts set their own cookie
+                    if !resp.http_headers.contains_key("set-cookie") {
+                        resp.http_headers.insert("set-cookie", cookie_str);
+                    }
+                }
                 Ok::<_, Infallible>(GraphQLResponse::from(resp))
             },
         );
@@ -111,7 +300,7 @@ async fn run() -> Result<()> {
 
     let cors = warp::cors()
         .allow_methods(&[Method::GET, Method::POST])
-        .allow_headers(["cookie", "content-type"])
+        .allow_headers(["cookie", "content-type", "authorization"])
         .allow_origins([
             "http://localhost:3000",
             "http://localhost:3003",
@@ -123,6 +312,7 @@ async fn run() -> Result<()> {
         .or(graphql_options)
         .or(favicon)
         .or(status)
+        .or(share)
         .with(cors)
         .with(warp::trace::request());
 
@@ -143,3 +333,52 @@ async fn run() -> Result<()> {
         .await;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn client_ip_ignores_forwarded_from_untrusted() {
+        let trusted = [ip("10.0.0.1")];
+        assert_eq!(
+            client_ip(Some(ip("1.2.3.4")), Some("5.6.7.8"), &trusted),
+            Some(ip("1.2.3.4"))
+        );
+        assert_eq!(
+            client_ip(Some(ip("1.2.3.4")), Some("5.6.7.8"), &[]),
+            Some(ip("1.2.3.4"))
+        );
+    }
+
+    #[test]
+    fn client_ip_takes_right_most_untrusted_hop() {
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+        assert_eq!(
+            client_ip(
+                Some(ip("10.0.0.1")),
+                Some("9.9.9.9, 5.6.7.8, 10.0.0.2"),
+                &trusted
+            ),
+            Some(ip("5.6.7.8"))
+        );
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), None, &trusted),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn client_ip_stops_at_garbage() {
+        let trusted = [ip("10.0.0.1")];
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), Some("5.6.7.8, nope"), &trusted),
+            Some(ip("10.0.0.1"))
+        );
+        assert_eq!(client_ip(None, Some("5.6.7.8"), &trusted), None);
+    }
+}
@@ -1,7 +1,17 @@
-use crate::loaders::{AppLoader, CreatureUserId, CreaturesForUserId, PoopsForCreatureId, UserId};
+use crate::crypto::{Enc, PasswordParams};
+use crate::loaders::{
+    ApiTokensForUserId, AppLoader, CreatureUserId, CreaturesForUserId, EventsPage,
+    GrantsForCreatureId, MembersForCreatureId, PendingInvitesForUserId, PoopsForCreatureId,
+    PoopsPage, SessionsForUserId, ShareLinksForCreatureId, UserId, WeightsForCreatureId,
+};
+use crate::schema::CreatureRoleGuard;
 use crate::AppError;
-use async_graphql::{Context, ErrorExtensions, FieldResult, Object};
-use chrono::{DateTime, Utc};
+use async_graphql::connection::{self, Connection, Edge};
+use async_graphql::{
+    Context, Enum, ErrorExtensions, FieldResult, InputObject, MaybeUndefined, Object,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::types::Json;
 
 #[derive(Clone, sqlx::FromRow)]
 pub struct User {
@@ -10,6 +20,11 @@ pub struct User {
     pub name: String,
     pub pw_salt: String,
     pub pw_hash: String,
+    pub pw_params: Json<PasswordParams>,
+    pub verified_at: Option<DateTime<Utc>>,
+    pub totp_secret: Option<Json<Enc>>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    #[allow(unused)]
     pub deleted: bool,
     pub created: DateTime<Utc>,
     pub modified: DateTime<Utc>,
@@ -26,6 +41,12 @@ impl User {
     async fn name(&self) -> &str {
         &self.name
     }
+    async fn verified_at(&self) -> Option<DateTime<Utc>> {
+        self.verified_at
+    }
+    async fn totp_enabled(&self) -> bool {
+        self.totp_enabled_at.is_some()
+    }
     async fn creatures(&self, ctx: &Context<'_>) -> FieldResult<Vec<CreatureRelation>> {
         let r = ctx
             .data_unchecked::<AppLoader>()
@@ -34,6 +55,33 @@ impl User {
             .unwrap_or_else(Vec::new);
         Ok(r)
     }
+    async fn pending_invites(&self, ctx: &Context<'_>) -> FieldResult<Vec<CreatureInvite>> {
+        require_self(ctx, self.id)?;
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(PendingInvitesForUserId(self.id))
+            .await?
+            .unwrap_or_else(Vec::new);
+        Ok(r)
+    }
+    async fn sessions(&self, ctx: &Context<'_>) -> FieldResult<Vec<AuthToken>> {
+        require_self(ctx, self.id)?;
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(SessionsForUserId(self.id))
+            .await?
+            .unwrap_or_else(Vec::new);
+        Ok(r)
+    }
+    async fn api_tokens(&self, ctx: &Context<'_>) -> FieldResult<Vec<ApiToken>> {
+        require_self(ctx, self.id)?;
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(ApiTokensForUserId(self.id))
+            .await?
+            .unwrap_or_else(Vec::new);
+        Ok(r)
+    }
     async fn created(&self) -> DateTime<Utc> {
         self.created
     }
@@ -42,6 +90,212 @@ impl User {
     }
 }
 
+/// Either the logged in user, or a challenge that has to be
+/// completed with `completeLogin` when they have 2FA enabled
+pub struct LoginResult {
+    pub user: Option<User>,
+    pub challenge: Option<String>,
+}
+
+#[Object]
+impl LoginResult {
+    async fn user(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+    async fn challenge(&self) -> Option<&str> {
+        self.challenge.as_deref()
+    }
+}
+
+pub struct TotpSetup {
+    pub secret: String,
+    pub uri: String,
+}
+
+#[Object]
+impl TotpSetup {
+    /// base32 secret, for entering into an authenticator app by hand
+    async fn secret(&self) -> &str {
+        &self.secret
+    }
+    /// otpauth:// uri, for rendering as a QR code
+    async fn uri(&self) -> &str {
+        &self.uri
+    }
+}
+
+/// Private user fields are only visible to that user
+fn require_self(ctx: &Context<'_>, user_id: i64) -> FieldResult<()> {
+    match ctx.data_opt::<User>() {
+        Some(u) if u.id == user_id => Ok(()),
+        _ => Err(AppError::Forbidden("Forbidden".into()).extend()),
+    }
+}
+
+/// Metadata about the incoming request, recorded with new sessions
+#[derive(Clone, Default)]
+pub struct RequestMeta {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+#[derive(Clone, sqlx::FromRow)]
+pub struct AuthToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub expires: DateTime<Utc>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub last_seen: DateTime<Utc>,
+    pub created: DateTime<Utc>,
+}
+
+#[Object(name = "Session")]
+impl AuthToken {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    /// Whether this is the session making the current request
+    async fn current(&self, ctx: &Context<'_>) -> bool {
+        ctx.data_opt::<AuthToken>()
+            .map(|t| t.id == self.id)
+            .unwrap_or(false)
+    }
+    async fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+    async fn ip(&self) -> Option<&str> {
+        self.ip.as_deref()
+    }
+    async fn last_seen(&self) -> DateTime<Utc> {
+        self.last_seen
+    }
+    async fn expires(&self) -> DateTime<Utc> {
+        self.expires
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+}
+
+/// What a personal api token is allowed to do. `Write` includes `Read`.
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiTokenScope {
+    Read,
+    Write,
+}
+impl ApiTokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+        }
+    }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub scope: String,
+    pub expires: Option<DateTime<Utc>>,
+    pub last_used: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+}
+impl ApiToken {
+    pub fn scope(&self) -> ApiTokenScope {
+        match self.scope.as_str() {
+            "write" => ApiTokenScope::Write,
+            _ => ApiTokenScope::Read,
+        }
+    }
+}
+
+#[Object]
+impl ApiToken {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn name(&self) -> &str {
+        &self.name
+    }
+    #[graphql(name = "scope")]
+    async fn gql_scope(&self) -> ApiTokenScope {
+        self.scope()
+    }
+    async fn expires(&self) -> Option<DateTime<Utc>> {
+        self.expires
+    }
+    async fn last_used(&self) -> Option<DateTime<Utc>> {
+        self.last_used
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+}
+
+/// A newly created api token. The token itself is only ever shown here.
+pub struct CreatedApiToken {
+    pub token: String,
+    pub api_token: ApiToken,
+}
+
+#[Object]
+impl CreatedApiToken {
+    async fn token(&self) -> &str {
+        &self.token
+    }
+    async fn api_token(&self) -> &ApiToken {
+        &self.api_token
+    }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+pub struct ShareLink {
+    pub id: i64,
+    pub creature_id: i64,
+    pub name: String,
+    pub expires: Option<DateTime<Utc>>,
+    pub last_viewed: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+}
+
+#[Object]
+impl ShareLink {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn name(&self) -> &str {
+        &self.name
+    }
+    async fn expires(&self) -> Option<DateTime<Utc>> {
+        self.expires
+    }
+    async fn last_viewed(&self) -> Option<DateTime<Utc>> {
+        self.last_viewed
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+}
+
+/// A newly created share link. The url is only ever shown here.
+pub struct CreatedShareLink {
+    pub url: String,
+    pub share_link: ShareLink,
+}
+
+#[Object]
+impl CreatedShareLink {
+    async fn url(&self) -> &str {
+        &self.url
+    }
+    async fn share_link(&self) -> &ShareLink {
+        &self.share_link
+    }
+}
+
 #[derive(Clone, sqlx::FromRow)]
 pub struct SimpleUser {
     pub id: i64,
@@ -65,6 +319,170 @@ impl SimpleUser {
     }
 }
 
+/// A user's level of access to a creature, ordered from least to most
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CreatureAccessKind {
+    Reader,
+    Pooper,
+    Creator,
+}
+impl CreatureAccessKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reader => "reader",
+            Self::Pooper => "pooper",
+            Self::Creator => "creator",
+        }
+    }
+    /// Unknown kinds are treated as the least privileged
+    pub fn from_db(kind: &str) -> Self {
+        match kind {
+            "creator" => Self::Creator,
+            "pooper" => Self::Pooper,
+            _ => Self::Reader,
+        }
+    }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+pub struct CreatureInvite {
+    pub id: i64,
+    pub creature_id: i64,
+    pub creature_name: String,
+    pub user_id: i64,
+    pub creator_id: i64,
+    pub kind: String,
+    pub expires: DateTime<Utc>,
+    pub created: DateTime<Utc>,
+}
+
+#[Object]
+impl CreatureInvite {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn creature_id(&self) -> String {
+        self.creature_id.to_string()
+    }
+    async fn creature_name(&self) -> &str {
+        &self.creature_name
+    }
+    async fn invited_by(&self, ctx: &Context<'_>) -> FieldResult<SimpleUser> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(UserId(self.creator_id))
+            .await?
+            .ok_or_else(|| {
+                AppError::E(format!(
+                    "missing expected creator {} of invite {}",
+                    self.creator_id, self.id
+                ))
+                .extend()
+            })?
+            .into();
+        Ok(r)
+    }
+    async fn kind(&self) -> CreatureAccessKind {
+        CreatureAccessKind::from_db(&self.kind)
+    }
+    async fn expires(&self) -> DateTime<Utc> {
+        self.expires
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+}
+
+/// Where temporary access is relative to now
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessStatus {
+    Upcoming,
+    Active,
+    Expired,
+}
+
+/// A user with access to a creature. Temporary access has an `expires`
+/// and optionally a `starts`.
+#[derive(Clone, sqlx::FromRow)]
+pub struct CreatureMember {
+    pub creature_id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub kind: String,
+    pub starts: Option<DateTime<Utc>>,
+    pub expires: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+}
+
+#[Object]
+impl CreatureMember {
+    async fn user(&self) -> SimpleUser {
+        SimpleUser {
+            id: self.user_id,
+            name: self.name.clone(),
+        }
+    }
+    async fn kind(&self) -> CreatureAccessKind {
+        CreatureAccessKind::from_db(&self.kind)
+    }
+    async fn starts(&self) -> Option<DateTime<Utc>> {
+        self.starts
+    }
+    async fn expires(&self) -> Option<DateTime<Utc>> {
+        self.expires
+    }
+    async fn status(&self) -> AccessStatus {
+        let now = Utc::now();
+        if self.expires.map(|e| e <= now).unwrap_or(false) {
+            AccessStatus::Expired
+        } else if self.starts.map(|s| s > now).unwrap_or(false) {
+            AccessStatus::Upcoming
+        } else {
+            AccessStatus::Active
+        }
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+}
+
+const POOPS_PAGE_SIZE: usize = 50;
+const POOPS_PAGE_MAX: usize = 200;
+
+const EVENTS_PAGE_SIZE: i32 = 50;
+const EVENTS_PAGE_MAX: i32 = 200;
+
+/// Cursors are poop ids, make sure one is for a live poop of this
+/// creature before paging from it
+async fn check_poop_cursor(
+    pool: &sqlx::PgPool,
+    creature_id: i64,
+    cursor: i64,
+) -> crate::Result<()> {
+    #[derive(sqlx::FromRow)]
+    struct Found {
+        found: bool,
+    }
+    let found: Found = sqlx::query_as(
+        r##"
+        select exists(
+            select 1 from poop.poops
+            where id = $1
+                and creature_id = $2
+                and deleted is false
+        ) as found
+        "##,
+    )
+    .bind(cursor)
+    .bind(creature_id)
+    .fetch_one(pool)
+    .await?;
+    if !found.found {
+        return Err(AppError::BadRequest("invalid cursor".into()));
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct CreatureRelation {
     pub id: i64,
@@ -72,6 +490,13 @@ pub struct CreatureRelation {
     pub kind: String,
     pub creator_id: i64,
     pub name: String,
+    pub species: Option<String>,
+    pub breed: Option<String>,
+    pub birthdate: Option<NaiveDate>,
+    pub sex: Option<String>,
+    pub initial_weight_grams: Option<f64>,
+    pub photo_url: Option<String>,
+    #[allow(unused)]
     pub deleted: bool,
     pub created: DateTime<Utc>,
     pub modified: DateTime<Utc>,
@@ -103,14 +528,389 @@ impl CreatureRelation {
     async fn name(&self) -> &str {
         &self.name
     }
-    async fn poops(&self, ctx: &Context<'_>) -> FieldResult<Vec<Poop>> {
+    /// One of the `species` ids
+    async fn species(&self) -> Option<&str> {
+        self.species.as_deref()
+    }
+    async fn breed(&self) -> Option<&str> {
+        self.breed.as_deref()
+    }
+    async fn birthdate(&self) -> Option<NaiveDate> {
+        self.birthdate
+    }
+    async fn sex(&self) -> Option<CreatureSex> {
+        self.sex.as_deref().and_then(CreatureSex::from_db)
+    }
+    /// Weight when the profile was filled in
+    async fn initial_weight_grams(&self) -> Option<f64> {
+        self.initial_weight_grams
+    }
+    async fn photo_url(&self) -> Option<&str> {
+        self.photo_url.as_deref()
+    }
+    #[graphql(guard = "CreatureRoleGuard::id(self.id, CreatureAccessKind::Reader)")]
+    async fn members(&self, ctx: &Context<'_>) -> FieldResult<Vec<CreatureMember>> {
         let r = ctx
             .data_unchecked::<AppLoader>()
-            .load_one(PoopsForCreatureId(self.id))
+            .load_one(MembersForCreatureId(self.id))
             .await?
             .unwrap_or_else(Vec::new);
         Ok(r)
     }
+    /// Temporary access, including upcoming and expired grants
+    #[graphql(guard = "CreatureRoleGuard::id(self.id, CreatureAccessKind::Creator)")]
+    async fn grants(&self, ctx: &Context<'_>) -> FieldResult<Vec<CreatureMember>> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(GrantsForCreatureId(self.id))
+            .await?
+            .unwrap_or_else(Vec::new);
+        Ok(r)
+    }
+    #[graphql(guard = "CreatureRoleGuard::id(self.id, CreatureAccessKind::Creator)")]
+    async fn share_links(&self, ctx: &Context<'_>) -> FieldResult<Vec<ShareLink>> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(ShareLinksForCreatureId(self.id))
+            .await?
+            .unwrap_or_else(Vec::new);
+        Ok(r)
+    }
+    /// Logged events newest first, optionally only of the given kinds.
+    /// Loads `first` events, up to 200, from just past the `after` event.
+    #[graphql(guard = "CreatureRoleGuard::id(self.id, CreatureAccessKind::Reader)")]
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        kinds: Option<Vec<String>>,
+        #[graphql(default_with = "EVENTS_PAGE_SIZE")] first: i32,
+        after: Option<String>,
+    ) -> FieldResult<Vec<Event>> {
+        if !(1..=EVENTS_PAGE_MAX).contains(&first) {
+            return Err(AppError::BadRequest(format!(
+                "first must be between 1 and {EVENTS_PAGE_MAX}"
+            ))
+            .extend());
+        }
+        let after = after.map(|id| id.parse::<i64>()).transpose()?;
+        let kinds = kinds.map(|mut kinds| {
+            kinds.sort();
+            kinds.dedup();
+            kinds
+        });
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(EventsPage {
+                creature_id: self.id,
+                kinds,
+                after,
+                limit: first as i64,
+            })
+            .await?
+            .unwrap_or_else(Vec::new);
+        Ok(r)
+    }
+    #[graphql(
+        guard = "CreatureRoleGuard::id(self.id, CreatureAccessKind::Reader)",
+        deprecation = "loads every poop, use poopsConnection"
+    )]
+    async fn poops(&self, ctx: &Context<'_>, filter: Option<PoopFilter>) -> FieldResult<Vec<Poop>> {
+        let filter = filter.unwrap_or_default().normalized();
+        filter.validate().map_err(|e| e.extend())?;
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(PoopsForCreatureId(self.id, filter))
+            .await?
+            .unwrap_or_else(Vec::new);
+        Ok(r)
+    }
+    /// Poops newest first, paged with relay cursors. Defaults to the
+    /// first 50.
+    #[graphql(guard = "CreatureRoleGuard::id(self.id, CreatureAccessKind::Reader)")]
+    async fn poops_connection(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<PoopFilter>,
+    ) -> FieldResult<Connection<i64, Poop>> {
+        let filter = filter.unwrap_or_default().normalized();
+        filter.validate().map_err(|e| e.extend())?;
+        connection::query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let backward = last.is_some();
+                let size = last.or(first).unwrap_or(POOPS_PAGE_SIZE);
+                if size > POOPS_PAGE_MAX {
+                    return Err(AppError::BadRequest(format!(
+                        "can't load more than {POOPS_PAGE_MAX} poops at a time"
+                    ))
+                    .extend());
+                }
+                let pool = ctx.data_unchecked::<sqlx::PgPool>();
+                for cursor in after.into_iter().chain(before) {
+                    check_poop_cursor(pool, self.id, cursor)
+                        .await
+                        .map_err(|e| e.extend())?;
+                }
+                // one extra to see if there's another page
+                let mut poops = ctx
+                    .data_unchecked::<AppLoader>()
+                    .load_one(PoopsPage {
+                        creature_id: self.id,
+                        filter,
+                        after,
+                        before,
+                        limit: size as i64 + 1,
+                        backward,
+                    })
+                    .await?
+                    .unwrap_or_else(Vec::new);
+                let more = poops.len() > size;
+                poops.truncate(size);
+                if backward {
+                    poops.reverse();
+                }
+                // past the cursor there's always at least the cursor's poop
+                let (has_previous, has_next) = if backward {
+                    (more, before.is_some())
+                } else {
+                    (after.is_some(), more)
+                };
+                let mut c = Connection::new(has_previous, has_next);
+                c.append(poops.into_iter().map(|p| Edge::new(p.id, p)));
+                Ok(c)
+            },
+        )
+        .await
+    }
+    /// Poop statistics for the `window` ending at the end of `end`'s day
+    /// (UTC), compared with the window before it. Defaults to the last week,
+    /// including today.
+    #[graphql(guard = "CreatureRoleGuard::id(self.id, CreatureAccessKind::Reader)")]
+    async fn stats(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "StatsWindow::Week")] window: StatsWindow,
+        end: Option<DateTime<Utc>>,
+    ) -> FieldResult<PoopStats> {
+        let pool = ctx.data_unchecked::<sqlx::PgPool>();
+        let end = end.unwrap_or_else(Utc::now);
+        Ok(crate::stats::poop_stats(pool, self.id, window, end).await?)
+    }
+    /// Weights measured in `[from, to)`, oldest first
+    #[graphql(guard = "CreatureRoleGuard::id(self.id, CreatureAccessKind::Reader)")]
+    async fn weights(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> FieldResult<Vec<Weight>> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(WeightsForCreatureId(self.id, from, to))
+            .await?
+            .unwrap_or_else(Vec::new);
+        Ok(r)
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+    async fn modified(&self) -> DateTime<Utc> {
+        self.modified
+    }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+pub struct Species {
+    pub species: String,
+    pub name: String,
+}
+
+#[Object]
+impl Species {
+    async fn id(&self) -> &str {
+        &self.species
+    }
+    async fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CreatureSex {
+    Male,
+    Female,
+}
+impl CreatureSex {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Male => "male",
+            Self::Female => "female",
+        }
+    }
+    pub fn from_db(sex: &str) -> Option<Self> {
+        Some(match sex {
+            "male" => Self::Male,
+            "female" => Self::Female,
+            _ => return None,
+        })
+    }
+}
+
+/// Profile details for `createCreature` and `updateCreatureProfile`.
+/// Omitted fields are left alone and fields set to `null` are cleared.
+#[derive(InputObject, Default)]
+pub struct CreatureProfileInput {
+    /// One of the `species` ids
+    pub species: MaybeUndefined<String>,
+    pub breed: MaybeUndefined<String>,
+    pub birthdate: MaybeUndefined<NaiveDate>,
+    pub sex: MaybeUndefined<CreatureSex>,
+    pub initial_weight_grams: MaybeUndefined<f64>,
+    pub photo_url: MaybeUndefined<String>,
+}
+
+const BREED_MAX_LEN: usize = 100;
+const PHOTO_URL_MAX_LEN: usize = 2000;
+
+impl CreatureProfileInput {
+    pub fn validate(&self) -> crate::Result<()> {
+        if let MaybeUndefined::Value(breed) = &self.breed {
+            if breed.chars().count() > BREED_MAX_LEN {
+                return Err(AppError::BadRequest(format!(
+                    "breed can't be longer than {BREED_MAX_LEN} characters"
+                )));
+            }
+        }
+        if let MaybeUndefined::Value(birthdate) = self.birthdate {
+            if birthdate > Utc::now().date().naive_utc() + chrono::Duration::days(1) {
+                return Err(AppError::BadRequest(
+                    "birthdate can't be in the future".into(),
+                ));
+            }
+        }
+        if let MaybeUndefined::Value(grams) = self.initial_weight_grams {
+            if !grams.is_finite() || grams <= 0.0 {
+                return Err(AppError::BadRequest(
+                    "initialWeightGrams must be greater than 0".into(),
+                ));
+            }
+        }
+        if let MaybeUndefined::Value(url) = &self.photo_url {
+            let url = url.trim();
+            if !(url.starts_with("https://") || url.starts_with("http://"))
+                || url.len() > PHOTO_URL_MAX_LEN
+            {
+                return Err(AppError::BadRequest(
+                    "photoUrl must be an http(s) url".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the provided fields to `creature`
+    pub fn apply(self, creature: &mut CreatureRelation) {
+        fn update<T>(field: &mut Option<T>, value: MaybeUndefined<T>) {
+            match value {
+                MaybeUndefined::Undefined => (),
+                MaybeUndefined::Null => *field = None,
+                MaybeUndefined::Value(v) => *field = Some(v),
+            }
+        }
+        fn trimmed(value: MaybeUndefined<String>) -> MaybeUndefined<String> {
+            match value {
+                MaybeUndefined::Value(v) if v.trim().is_empty() => MaybeUndefined::Null,
+                value => value.map_value(|v| v.trim().to_string()),
+            }
+        }
+        update(&mut creature.species, self.species);
+        update(&mut creature.breed, trimmed(self.breed));
+        update(&mut creature.birthdate, self.birthdate);
+        update(
+            &mut creature.sex,
+            self.sex.map_value(|s| s.as_str().to_string()),
+        );
+        update(
+            &mut creature.initial_weight_grams,
+            self.initial_weight_grams,
+        );
+        update(&mut creature.photo_url, trimmed(self.photo_url));
+    }
+}
+
+/// A kind of event that can be logged, and the JSON schema its attrs
+/// have to match
+#[derive(Clone, sqlx::FromRow)]
+pub struct EventKind {
+    pub kind: String,
+    pub attrs_schema: Json<serde_json::Value>,
+}
+
+#[Object]
+impl EventKind {
+    async fn kind(&self) -> &str {
+        &self.kind
+    }
+    async fn attrs_schema(&self) -> async_graphql::Json<&serde_json::Value> {
+        async_graphql::Json(&self.attrs_schema.0)
+    }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+pub struct Event {
+    pub id: i64,
+    pub creator_id: i64,
+    #[allow(unused)]
+    pub creature_id: i64,
+    pub kind: String,
+    pub occurred_at: DateTime<Utc>,
+    pub attrs: Json<serde_json::Value>,
+    pub notes: Option<String>,
+    #[allow(unused)]
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+}
+
+#[Object]
+impl Event {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn kind(&self) -> &str {
+        &self.kind
+    }
+    async fn creator(&self, ctx: &Context<'_>) -> FieldResult<SimpleUser> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(UserId(self.creator_id))
+            .await?
+            .ok_or_else(|| {
+                AppError::E(format!(
+                    "missing expected creator {} of event {}",
+                    self.creator_id, self.id
+                ))
+                .extend()
+            })?
+            .into();
+        Ok(r)
+    }
+    async fn occurred_at(&self) -> DateTime<Utc> {
+        self.occurred_at
+    }
+    async fn attrs(&self) -> async_graphql::Json<&serde_json::Value> {
+        async_graphql::Json(&self.attrs.0)
+    }
+    async fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
     async fn created(&self) -> DateTime<Utc> {
         self.created
     }
@@ -119,11 +919,242 @@ impl CreatureRelation {
     }
 }
 
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PoopColor {
+    Brown,
+    DarkBrown,
+    Yellow,
+    Green,
+    Orange,
+    Red,
+    Black,
+    Gray,
+}
+impl PoopColor {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Brown => "brown",
+            Self::DarkBrown => "dark_brown",
+            Self::Yellow => "yellow",
+            Self::Green => "green",
+            Self::Orange => "orange",
+            Self::Red => "red",
+            Self::Black => "black",
+            Self::Gray => "gray",
+        }
+    }
+    pub fn from_db(color: &str) -> Option<Self> {
+        Some(match color {
+            "brown" => Self::Brown,
+            "dark_brown" => Self::DarkBrown,
+            "yellow" => Self::Yellow,
+            "green" => Self::Green,
+            "orange" => Self::Orange,
+            "red" => Self::Red,
+            "black" => Self::Black,
+            "gray" => Self::Gray,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PoopSize {
+    Small,
+    Medium,
+    Large,
+}
+impl PoopSize {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::Large => "large",
+        }
+    }
+    pub fn from_db(size: &str) -> Option<Self> {
+        Some(match size {
+            "small" => Self::Small,
+            "medium" => Self::Medium,
+            "large" => Self::Large,
+            _ => return None,
+        })
+    }
+}
+
+/// Poop details for `createPoop` and `updatePoop`. On update, omitted
+/// fields are left alone and nullable fields set to `null` are cleared.
+#[derive(InputObject, Default)]
+pub struct PoopInput {
+    pub occurred_at: Option<DateTime<Utc>>,
+    pub bristol: MaybeUndefined<i32>,
+    pub color: MaybeUndefined<PoopColor>,
+    pub size: MaybeUndefined<PoopSize>,
+    pub blood: Option<bool>,
+    pub mucus: Option<bool>,
+    pub notes: MaybeUndefined<String>,
+}
+
+/// Restricts the poops listed. Every provided field has to match.
+#[derive(InputObject, Clone, Default, Debug, PartialEq, Eq, Hash)]
+pub struct PoopFilter {
+    /// Occurred at or after
+    pub from: Option<DateTime<Utc>>,
+    /// Occurred before
+    pub to: Option<DateTime<Utc>>,
+    /// Logged by this user
+    pub creator_id: Option<String>,
+    pub bristol_min: Option<i32>,
+    pub bristol_max: Option<i32>,
+    /// Any of these colors
+    pub colors: Option<Vec<PoopColor>>,
+    /// Any of these sizes
+    pub sizes: Option<Vec<PoopSize>>,
+    pub blood: Option<bool>,
+    pub mucus: Option<bool>,
+}
+
+impl PoopFilter {
+    pub fn validate(&self) -> crate::Result<()> {
+        if let Some(creator_id) = &self.creator_id {
+            creator_id
+                .parse::<i64>()
+                .map_err(|_| AppError::BadRequest("invalid creatorId".into()))?;
+        }
+        for bristol in [self.bristol_min, self.bristol_max].into_iter().flatten() {
+            if !(1..=7).contains(&bristol) {
+                return Err(AppError::BadRequest(
+                    "bristol must be between 1 and 7".into(),
+                ));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.bristol_min, self.bristol_max) {
+            if min > max {
+                return Err(AppError::BadRequest(
+                    "bristolMin can't be greater than bristolMax".into(),
+                ));
+            }
+        }
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from >= to {
+                return Err(AppError::BadRequest("from must be before to".into()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Equivalent filters make the same loader key
+    fn normalized(mut self) -> Self {
+        if let Some(colors) = self.colors.as_mut() {
+            colors.sort_by_key(|c| c.as_str());
+            colors.dedup();
+        }
+        if let Some(sizes) = self.sizes.as_mut() {
+            sizes.sort_by_key(|s| s.as_str());
+            sizes.dedup();
+        }
+        self
+    }
+}
+
+const NOTES_MAX_LEN: usize = 2000;
+
+/// Events can be backdated but not logged ahead of time. A little slack
+/// is allowed for clocks that are off.
+const OCCURRED_AT_MAX_SKEW_SECONDS: i64 = 300;
+
+fn check_not_future(field: &str, at: DateTime<Utc>) -> crate::Result<()> {
+    if at > Utc::now() + chrono::Duration::seconds(OCCURRED_AT_MAX_SKEW_SECONDS) {
+        return Err(AppError::BadRequest(format!(
+            "{field} can't be in the future"
+        )));
+    }
+    Ok(())
+}
+
+pub fn check_occurred_at(occurred_at: DateTime<Utc>) -> crate::Result<()> {
+    check_not_future("occurredAt", occurred_at)
+}
+
+pub fn check_measured_at(measured_at: DateTime<Utc>) -> crate::Result<()> {
+    check_not_future("measuredAt", measured_at)
+}
+
+pub fn check_notes(notes: &str) -> crate::Result<()> {
+    if notes.chars().count() > NOTES_MAX_LEN {
+        return Err(AppError::BadRequest(format!(
+            "notes can't be longer than {NOTES_MAX_LEN} characters"
+        )));
+    }
+    Ok(())
+}
+
+impl PoopInput {
+    pub fn validate(&self) -> crate::Result<()> {
+        if let Some(occurred_at) = self.occurred_at {
+            check_occurred_at(occurred_at)?;
+        }
+        if let MaybeUndefined::Value(bristol) = self.bristol {
+            if !(1..=7).contains(&bristol) {
+                return Err(AppError::BadRequest(
+                    "bristol must be between 1 and 7".into(),
+                ));
+            }
+        }
+        if let MaybeUndefined::Value(notes) = &self.notes {
+            check_notes(notes)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the provided fields to `poop`
+    pub fn apply(self, poop: &mut Poop) {
+        if let Some(occurred_at) = self.occurred_at {
+            poop.occurred_at = occurred_at;
+        }
+        if let Some(blood) = self.blood {
+            poop.blood = blood;
+        }
+        if let Some(mucus) = self.mucus {
+            poop.mucus = mucus;
+        }
+        fn update<T>(field: &mut Option<T>, value: MaybeUndefined<T>) {
+            match value {
+                MaybeUndefined::Undefined => (),
+                MaybeUndefined::Null => *field = None,
+                MaybeUndefined::Value(v) => *field = Some(v),
+            }
+        }
+        update(&mut poop.bristol, self.bristol.map_value(|b| b as i16));
+        update(
+            &mut poop.color,
+            self.color.map_value(|c| c.as_str().to_string()),
+        );
+        update(
+            &mut poop.size,
+            self.size.map_value(|s| s.as_str().to_string()),
+        );
+        let notes = match self.notes {
+            MaybeUndefined::Value(n) if n.trim().is_empty() => MaybeUndefined::Null,
+            notes => notes.map_value(|n| n.trim().to_string()),
+        };
+        update(&mut poop.notes, notes);
+    }
+}
+
 #[derive(Clone, sqlx::FromRow)]
 pub struct Poop {
     pub id: i64,
     pub creator_id: i64,
     pub creature_id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub bristol: Option<i16>,
+    pub color: Option<String>,
+    pub size: Option<String>,
+    pub blood: bool,
+    pub mucus: bool,
+    pub notes: Option<String>,
+    #[allow(unused)]
     pub deleted: bool,
     pub created: DateTime<Utc>,
     pub modified: DateTime<Utc>,
@@ -149,20 +1180,153 @@ impl Poop {
             .into();
         Ok(r)
     }
+    /// The current user's relation to the creature. The poop's creator
+    /// may no longer have access, e.g. once a sitter's grant expires.
     async fn creature(&self, ctx: &Context<'_>) -> FieldResult<CreatureRelation> {
+        let user = ctx
+            .data_opt::<User>()
+            .ok_or_else(|| AppError::Unauthorized("Unauthorized".into()).extend())?;
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(CreatureUserId(self.creature_id, user.id))
+            .await?
+            .ok_or_else(|| {
+                AppError::Forbidden("reader access to creature required".into()).extend()
+            })?;
+        Ok(r)
+    }
+    async fn occurred_at(&self) -> DateTime<Utc> {
+        self.occurred_at
+    }
+    /// Bristol stool scale, 1 (hard lumps) to 7 (entirely liquid)
+    async fn bristol(&self) -> Option<i32> {
+        self.bristol.map(i32::from)
+    }
+    async fn color(&self) -> Option<PoopColor> {
+        self.color.as_deref().and_then(PoopColor::from_db)
+    }
+    async fn size(&self) -> Option<PoopSize> {
+        self.size.as_deref().and_then(PoopSize::from_db)
+    }
+    async fn blood(&self) -> bool {
+        self.blood
+    }
+    async fn mucus(&self) -> bool {
+        self.mucus
+    }
+    async fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+    async fn modified(&self) -> DateTime<Utc> {
+        self.modified
+    }
+}
+
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeightUnit {
+    G,
+    Kg,
+    Lb,
+    Oz,
+}
+impl WeightUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::G => "g",
+            Self::Kg => "kg",
+            Self::Lb => "lb",
+            Self::Oz => "oz",
+        }
+    }
+    pub fn from_db(unit: &str) -> Option<Self> {
+        Some(match unit {
+            "g" => Self::G,
+            "kg" => Self::Kg,
+            "lb" => Self::Lb,
+            "oz" => Self::Oz,
+            _ => return None,
+        })
+    }
+    /// Matches the conversion behind `poop.weights.grams`
+    pub fn grams(&self) -> f64 {
+        match self {
+            Self::G => 1.0,
+            Self::Kg => 1000.0,
+            Self::Lb => 453.59237,
+            Self::Oz => 28.349523125,
+        }
+    }
+    /// Convert a weight in grams to this unit
+    pub fn convert_grams(&self, grams: f64) -> f64 {
+        grams / self.grams()
+    }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+pub struct Weight {
+    pub id: i64,
+    pub creator_id: i64,
+    #[allow(unused)]
+    pub creature_id: i64,
+    pub measured_at: DateTime<Utc>,
+    pub value: f64,
+    pub unit: String,
+    pub grams: f64,
+    /// Change since the previous measurement
+    pub growth_grams_per_day: Option<f64>,
+    #[allow(unused)]
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+}
+
+#[Object]
+impl Weight {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn creator(&self, ctx: &Context<'_>) -> FieldResult<SimpleUser> {
         let r = ctx
             .data_unchecked::<AppLoader>()
-            .load_one(CreatureUserId(self.creature_id, self.creator_id))
+            .load_one(UserId(self.creator_id))
             .await?
             .ok_or_else(|| {
                 AppError::E(format!(
-                    "missing expected creature {} -> user {} relation",
-                    self.creature_id, self.creator_id
+                    "missing expected creator {} of weight {}",
+                    self.creator_id, self.id
                 ))
                 .extend()
-            })?;
+            })?
+            .into();
         Ok(r)
     }
+    async fn measured_at(&self) -> DateTime<Utc> {
+        self.measured_at
+    }
+    /// The weight as it was recorded
+    async fn value(&self) -> f64 {
+        self.value
+    }
+    async fn unit(&self) -> FieldResult<WeightUnit> {
+        WeightUnit::from_db(&self.unit).ok_or_else(|| {
+            AppError::E(format!("unknown unit {} of weight {}", self.unit, self.id)).extend()
+        })
+    }
+    /// The weight converted to `unit`
+    async fn value_in(&self, unit: WeightUnit) -> f64 {
+        unit.convert_grams(self.grams)
+    }
+    /// Change per day in `unit` since the previous measurement, null
+    /// for the first one
+    async fn growth_rate(
+        &self,
+        #[graphql(default_with = "WeightUnit::G")] unit: WeightUnit,
+    ) -> Option<f64> {
+        self.growth_grams_per_day.map(|g| unit.convert_grams(g))
+    }
     async fn created(&self) -> DateTime<Utc> {
         self.created
     }
@@ -170,3 +1334,341 @@ impl Poop {
         self.modified
     }
 }
+
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StatsWindow {
+    Week,
+    Month,
+    Quarter,
+}
+impl StatsWindow {
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            Self::Week => chrono::Duration::days(7),
+            Self::Month => chrono::Duration::days(30),
+            Self::Quarter => chrono::Duration::days(90),
+        }
+    }
+}
+
+/// Poops in `[starts, ends)` and the time between them
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct WindowStats {
+    pub starts: DateTime<Utc>,
+    pub ends: DateTime<Utc>,
+    pub count: i64,
+    pub mean_interval_seconds: Option<f64>,
+    pub median_interval_seconds: Option<f64>,
+    pub longest_gap_seconds: Option<f64>,
+    pub longest_gap_from: Option<DateTime<Utc>>,
+    pub longest_gap_to: Option<DateTime<Utc>>,
+}
+
+#[Object]
+impl WindowStats {
+    async fn from(&self) -> DateTime<Utc> {
+        self.starts
+    }
+    async fn to(&self) -> DateTime<Utc> {
+        self.ends
+    }
+    async fn count(&self) -> i64 {
+        self.count
+    }
+    async fn per_day(&self) -> f64 {
+        let days = (self.ends - self.starts).num_seconds() as f64 / 86400.0;
+        if days > 0.0 {
+            self.count as f64 / days
+        } else {
+            0.0
+        }
+    }
+    /// Between consecutive poops, null with fewer than two
+    async fn mean_interval_hours(&self) -> Option<f64> {
+        self.mean_interval_seconds.map(|s| s / 3600.0)
+    }
+    async fn median_interval_hours(&self) -> Option<f64> {
+        self.median_interval_seconds.map(|s| s / 3600.0)
+    }
+    async fn longest_gap_hours(&self) -> Option<f64> {
+        self.longest_gap_seconds.map(|s| s / 3600.0)
+    }
+    /// When the poop before the longest gap happened
+    async fn longest_gap_from(&self) -> Option<DateTime<Utc>> {
+        self.longest_gap_from
+    }
+    /// When the poop ending the longest gap happened
+    async fn longest_gap_to(&self) -> Option<DateTime<Utc>> {
+        self.longest_gap_to
+    }
+}
+
+impl WindowStats {
+    /// Relative change in count from `previous`, `None` when it has none
+    pub fn change_from(&self, previous: &WindowStats) -> Option<f64> {
+        if previous.count == 0 {
+            return None;
+        }
+        Some((self.count - previous.count) as f64 / previous.count as f64)
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct DailyCount {
+    pub day: NaiveDate,
+    pub count: i64,
+}
+
+#[Object]
+impl DailyCount {
+    /// The UTC day
+    async fn day(&self) -> NaiveDate {
+        self.day
+    }
+    async fn count(&self) -> i64 {
+        self.count
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PoopStats {
+    pub window: StatsWindow,
+    pub current: WindowStats,
+    pub previous: WindowStats,
+    pub daily_counts: Vec<DailyCount>,
+}
+
+#[Object]
+impl PoopStats {
+    async fn window(&self) -> StatsWindow {
+        self.window
+    }
+    async fn current(&self) -> &WindowStats {
+        &self.current
+    }
+    /// The window of the same length right before `current`
+    async fn previous(&self) -> &WindowStats {
+        &self.previous
+    }
+    /// Poops per UTC day touched by `current`, including days without any
+    async fn daily_counts(&self) -> &[DailyCount] {
+        &self.daily_counts
+    }
+    /// Relative change in count from `previous` to `current`, null when
+    /// `previous` has none
+    async fn count_change(&self) -> Option<f64> {
+        self.current.change_from(&self.previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creature_profile_validate() {
+        assert!(CreatureProfileInput::default().validate().is_ok());
+        let valid = CreatureProfileInput {
+            breed: MaybeUndefined::Value("Beagle".into()),
+            birthdate: MaybeUndefined::Value(NaiveDate::from_ymd(2020, 4, 1)),
+            initial_weight_grams: MaybeUndefined::Value(250.0),
+            photo_url: MaybeUndefined::Value(" https://example.com/rex.jpg ".into()),
+            ..Default::default()
+        };
+        assert!(valid.validate().is_ok());
+        // clearing is always allowed
+        let cleared = CreatureProfileInput {
+            breed: MaybeUndefined::Null,
+            birthdate: MaybeUndefined::Null,
+            initial_weight_grams: MaybeUndefined::Null,
+            photo_url: MaybeUndefined::Null,
+            ..Default::default()
+        };
+        assert!(cleared.validate().is_ok());
+    }
+
+    fn bad_request(res: crate::Result<()>) -> String {
+        match res {
+            Err(AppError::BadRequest(msg)) => msg,
+            other => panic!("expected a bad request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn creature_profile_validate_rejects() {
+        let long_breed = CreatureProfileInput {
+            breed: MaybeUndefined::Value("x".repeat(BREED_MAX_LEN + 1)),
+            ..Default::default()
+        };
+        assert_eq!(
+            bad_request(long_breed.validate()),
+            "breed can't be longer than 100 characters"
+        );
+
+        let unborn = CreatureProfileInput {
+            birthdate: MaybeUndefined::Value(
+                Utc::now().date().naive_utc() + chrono::Duration::days(2),
+            ),
+            ..Default::default()
+        };
+        assert_eq!(
+            bad_request(unborn.validate()),
+            "birthdate can't be in the future"
+        );
+
+        for grams in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let input = CreatureProfileInput {
+                initial_weight_grams: MaybeUndefined::Value(grams),
+                ..Default::default()
+            };
+            assert_eq!(
+                bad_request(input.validate()),
+                "initialWeightGrams must be greater than 0",
+                "{grams}"
+            );
+        }
+
+        let too_long = format!("https://{}", "x".repeat(PHOTO_URL_MAX_LEN));
+        for url in [
+            "javascript:alert(1)",
+            "ftp://example.com/rex.jpg",
+            &too_long,
+        ] {
+            let input = CreatureProfileInput {
+                photo_url: MaybeUndefined::Value(url.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(
+                bad_request(input.validate()),
+                "photoUrl must be an http(s) url"
+            );
+        }
+    }
+
+    #[test]
+    fn poop_filter_validate() {
+        assert!(PoopFilter::default().validate().is_ok());
+        let now = Utc::now();
+        let valid = PoopFilter {
+            from: Some(now - chrono::Duration::days(1)),
+            to: Some(now),
+            creator_id: Some("157020512603602963".into()),
+            bristol_min: Some(3),
+            bristol_max: Some(3),
+            ..Default::default()
+        };
+        assert!(valid.validate().is_ok());
+        let not_an_id = PoopFilter {
+            creator_id: Some("me".into()),
+            ..Default::default()
+        };
+        assert_eq!(bad_request(not_an_id.validate()), "invalid creatorId");
+
+        for (bristol_min, bristol_max) in [(Some(0), None), (None, Some(8))] {
+            let out_of_scale = PoopFilter {
+                bristol_min,
+                bristol_max,
+                ..Default::default()
+            };
+            assert_eq!(
+                bad_request(out_of_scale.validate()),
+                "bristol must be between 1 and 7"
+            );
+        }
+
+        let inverted_bristol = PoopFilter {
+            bristol_min: Some(5),
+            bristol_max: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            bad_request(inverted_bristol.validate()),
+            "bristolMin can't be greater than bristolMax"
+        );
+
+        // `to` is exclusive, so an empty range is rejected too
+        for to in [now, now - chrono::Duration::days(1)] {
+            let inverted_range = PoopFilter {
+                from: Some(now),
+                to: Some(to),
+                ..Default::default()
+            };
+            assert_eq!(
+                bad_request(inverted_range.validate()),
+                "from must be before to"
+            );
+        }
+    }
+
+    #[test]
+    fn poop_filter_normalized() {
+        let a = PoopFilter {
+            colors: Some(vec![PoopColor::Red, PoopColor::Brown, PoopColor::Red]),
+            sizes: Some(vec![PoopSize::Small, PoopSize::Large]),
+            blood: Some(true),
+            ..Default::default()
+        };
+        let b = PoopFilter {
+            colors: Some(vec![PoopColor::Brown, PoopColor::Red]),
+            sizes: Some(vec![PoopSize::Large, PoopSize::Small, PoopSize::Small]),
+            blood: Some(true),
+            ..Default::default()
+        };
+        assert_ne!(a, b);
+        let (a, b) = (a.normalized(), b.normalized());
+        assert_eq!(a, b);
+        assert_eq!(a.colors, Some(vec![PoopColor::Brown, PoopColor::Red]));
+        assert_eq!(a.sizes, Some(vec![PoopSize::Large, PoopSize::Small]));
+        // an empty list is kept since it matches nothing rather than everything
+        let empty = PoopFilter {
+            colors: Some(vec![]),
+            ..Default::default()
+        };
+        assert_eq!(empty.clone().normalized(), empty);
+    }
+
+    #[test]
+    fn stats_window_duration() {
+        assert_eq!(StatsWindow::Week.duration(), chrono::Duration::days(7));
+        assert_eq!(StatsWindow::Month.duration(), chrono::Duration::days(30));
+        assert_eq!(StatsWindow::Quarter.duration(), chrono::Duration::days(90));
+    }
+
+    #[test]
+    fn stats_count_change() {
+        let window = |count| WindowStats {
+            starts: Utc::now(),
+            ends: Utc::now(),
+            count,
+            mean_interval_seconds: None,
+            median_interval_seconds: None,
+            longest_gap_seconds: None,
+            longest_gap_from: None,
+            longest_gap_to: None,
+        };
+        assert_eq!(window(0).change_from(&window(0)), None);
+        assert_eq!(window(5).change_from(&window(0)), None);
+        assert_eq!(window(4).change_from(&window(4)), Some(0.0));
+        assert_eq!(window(6).change_from(&window(4)), Some(0.5));
+        assert_eq!(window(0).change_from(&window(4)), Some(-1.0));
+    }
+
+    #[test]
+    fn weight_unit_conversions() {
+        assert_eq!(WeightUnit::G.grams(), 1.0);
+        assert_eq!(WeightUnit::Kg.grams(), 1000.0);
+        assert!((WeightUnit::Lb.grams() - 16.0 * WeightUnit::Oz.grams()).abs() < 1e-9);
+        assert_eq!(WeightUnit::Kg.convert_grams(2500.0), 2.5);
+        assert!((WeightUnit::Lb.convert_grams(453.59237) - 1.0).abs() < 1e-12);
+        assert!((WeightUnit::Oz.convert_grams(1000.0) - 35.27396195).abs() < 1e-6);
+        for unit in [
+            WeightUnit::G,
+            WeightUnit::Kg,
+            WeightUnit::Lb,
+            WeightUnit::Oz,
+        ] {
+            assert_eq!(WeightUnit::from_db(unit.as_str()), Some(unit));
+            assert!((unit.convert_grams(unit.grams() * 3.0) - 3.0).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+/*!
+Background cleanup of stale rows
+*/
+use crate::{Result, CONFIG};
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+/// Spawn a task that periodically purges auth tokens and login challenges
+/// that expired, or were deleted, longer than `auth_token_retention_seconds`
+/// ago, along with failed login attempts that have aged out
+pub fn spawn(pool: PgPool) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            CONFIG.auth_token_reap_interval_seconds,
+        ));
+        loop {
+            interval.tick().await;
+            match reap_expiring(&pool, "auth_tokens").await {
+                Ok(0) => tracing::debug!("no auth tokens to reap"),
+                Ok(count) => tracing::info!(count = %count, "reaped auth tokens"),
+                Err(e) => tracing::error!(error = ?e, "error reaping auth tokens"),
+            }
+            match reap_expiring(&pool, "login_challenges").await {
+                Ok(0) => tracing::debug!("no login challenges to reap"),
+                Ok(count) => tracing::info!(count = %count, "reaped login challenges"),
+                Err(e) => tracing::error!(error = ?e, "error reaping login challenges"),
+            }
+            match reap_login_attempts(&pool).await {
+                Ok(0) => tracing::debug!("no login attempts to reap"),
+                Ok(count) => tracing::info!(count = %count, "reaped login attempts"),
+                Err(e) => tracing::error!(error = ?e, "error reaping login attempts"),
+            }
+        }
+    })
+}
+
+/// Delete stale rows from `table`, one with `expires` and `deleted`
+/// columns, in batches of `auth_token_reap_batch_size`. Returns the total
+/// number deleted.
+async fn reap_expiring(pool: &PgPool, table: &'static str) -> Result<u64> {
+    let cutoff = Utc::now() - Duration::seconds(CONFIG.auth_token_retention_seconds as i64);
+    let query = format!(
+        r##"
+        delete from poop.{table}
+        where id in (
+            select id from poop.{table}
+            where (deleted is false and expires < $1)
+                or (deleted is true and modified < $1)
+            limit $2
+        )
+        "##
+    );
+    let mut total = 0;
+    loop {
+        let res = sqlx::query(&query)
+            .bind(cutoff)
+            .bind(CONFIG.auth_token_reap_batch_size)
+            .execute(pool)
+            .await?;
+        let count = res.rows_affected();
+        total += count;
+        tracing::debug!(count = %count, table = %table, "reaped batch");
+        if count < CONFIG.auth_token_reap_batch_size as u64 {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Delete tracked login failures that are older than the attempt window
+async fn reap_login_attempts(pool: &PgPool) -> Result<u64> {
+    let cutoff = Utc::now() - Duration::seconds(CONFIG.login_attempt_window_seconds as i64);
+    let res = sqlx::query("delete from poop.login_attempts where last_failure < $1")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected())
+}
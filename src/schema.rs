@@ -1,16 +1,43 @@
-use crate::models::{CreatureRelation, Poop, User};
+use crate::crypto::PasswordParams;
+use crate::loaders::{AppLoader, CreatureAccess, CreatureUserId};
+use crate::mailer::{AppMailer, Email};
+use crate::models::{
+    check_measured_at, check_notes, check_occurred_at, ApiToken, ApiTokenScope, AuthToken,
+    CreatedApiToken, CreatedShareLink, CreatureAccessKind, CreatureInvite, CreatureMember,
+    CreatureProfileInput, CreatureRelation, Event, EventKind, LoginResult, Poop, PoopInput,
+    RequestMeta, ShareLink, Species, TotpSetup, User, Weight, WeightUnit,
+};
+use crate::throttle::{AttemptKind, Throttle};
 use crate::{AppError, Result, CONFIG};
 use async_graphql::{
     Context, EmptySubscription, ErrorExtensions, FieldResult, Guard, Object, ResultExt,
 };
 use chrono::Utc;
+use sqlx::types::Json;
 use sqlx::PgPool;
 
-struct LoginGuard;
+/// Requires a logged in user. Requests authenticated with an api token
+/// must carry at least `scope`, and are rejected outright when `scope`
+/// is `None` (cookie sessions only).
+struct LoginGuard {
+    scope: Option<ApiTokenScope>,
+}
 
 impl LoginGuard {
     fn new() -> Self {
-        Self {}
+        Self {
+            scope: Some(ApiTokenScope::Read),
+        }
+    }
+
+    fn write() -> Self {
+        Self {
+            scope: Some(ApiTokenScope::Write),
+        }
+    }
+
+    fn session() -> Self {
+        Self { scope: None }
     }
 }
 
@@ -18,21 +45,77 @@ impl LoginGuard {
 impl Guard for LoginGuard {
     async fn check(&self, ctx: &Context<'_>) -> FieldResult<()> {
         if ctx.data_opt::<User>().is_none() {
-            Err(AppError::Unauthorized("Unauthorized".into()).extend())
-        } else {
-            Ok(())
+            return Err(AppError::Unauthorized("Unauthorized".into()).extend());
+        }
+        if let Some(token) = ctx.data_opt::<ApiToken>() {
+            match self.scope {
+                Some(scope) if token.scope() >= scope => (),
+                _ => return Err(AppError::Forbidden("Forbidden".into()).extend()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Requires the current user to have at least `min` access to a creature.
+/// The creature is either the resolver's `creature_id` argument or the
+/// parent's id.
+pub struct CreatureRoleGuard {
+    creature_id: Option<i64>,
+    min: CreatureAccessKind,
+}
+
+impl CreatureRoleGuard {
+    pub fn new(creature_id: &str, min: CreatureAccessKind) -> Self {
+        Self {
+            creature_id: creature_id.parse().ok(),
+            min,
+        }
+    }
+
+    pub fn id(creature_id: i64, min: CreatureAccessKind) -> Self {
+        Self {
+            creature_id: Some(creature_id),
+            min,
         }
     }
 }
 
-fn format_set_cookie(token: &str) -> String {
+#[async_trait::async_trait]
+impl Guard for CreatureRoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> FieldResult<()> {
+        let user = ctx
+            .data_opt::<User>()
+            .ok_or_else(|| AppError::Unauthorized("Unauthorized".into()).extend())?;
+        let creature_id = self
+            .creature_id
+            .ok_or_else(|| AppError::BadRequest("invalid creature id".into()).extend())?;
+        let kind = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(CreatureAccess(creature_id, user.id))
+            .await?;
+        check_creature_role(kind, self.min).map_err(|e| e.extend())
+    }
+}
+
+fn check_creature_role(kind: Option<CreatureAccessKind>, min: CreatureAccessKind) -> Result<()> {
+    match kind {
+        Some(kind) if kind >= min => Ok(()),
+        _ => Err(AppError::Forbidden(format!(
+            "{} access to creature required",
+            min.as_str()
+        ))),
+    }
+}
+
+pub fn format_set_cookie(token: &str, max_age: i64) -> String {
     format!(
         "{name}={token}; Domain={domain}; {secure} HttpOnly; Max-Age={max_age}; SameSite=Lax; Path=/",
         name = &CONFIG.cookie_name,
         token = token,
         domain = &CONFIG.get_real_domain(),
         secure = if CONFIG.secure_cookie { "Secure;" } else { "" },
-        max_age = &CONFIG.auth_expiration_seconds,
+        max_age = max_age,
     )
 }
 
@@ -40,28 +123,740 @@ async fn login_ctx(ctx: &Context<'_>, user: &User) -> Result<()> {
     let pool = ctx.data_unchecked::<PgPool>();
     let token = hex::encode(crate::crypto::rand_bytes(32)?);
     let token_hash = crate::crypto::hmac_sign(&token);
+    let lifetime = CONFIG
+        .auth_expiration_seconds
+        .min(CONFIG.auth_max_session_seconds) as i64;
     let expires = Utc::now()
-        .checked_add_signed(chrono::Duration::seconds(
-            CONFIG.auth_expiration_seconds as i64,
-        ))
+        .checked_add_signed(chrono::Duration::seconds(lifetime))
         .ok_or_else(|| AppError::from("error calculating auth expiration"))?;
+    let meta = ctx.data_opt::<RequestMeta>().cloned().unwrap_or_default();
     sqlx::query(
         r##"
         insert into poop.auth_tokens
-            (user_id, hash, expires) values ($1, $2, $3)
+            (user_id, hash, expires, user_agent, ip) values ($1, $2, $3, $4, $5)
     "##,
     )
-    .bind(&user.id)
+    .bind(user.id)
     .bind(token_hash)
     .bind(expires)
+    .bind(meta.user_agent)
+    .bind(meta.ip)
     .execute(pool)
     .await
     .map_err(AppError::from)?;
-    let cookie_str = format_set_cookie(&token);
+    let cookie_str = format_set_cookie(&token, lifetime);
     ctx.insert_http_header("set-cookie", cookie_str);
     Ok(())
 }
 
+/// A hex encoded salt and hash, along with the params used to make them
+struct PasswordHash {
+    salt: String,
+    hash: String,
+    params: Json<PasswordParams>,
+}
+
+/// Password hashing is deliberately slow, keep it off the async workers
+async fn hash_password_blocking(
+    pw: &str,
+    salt: Vec<u8>,
+    params: PasswordParams,
+) -> Result<[u8; ring::digest::SHA512_OUTPUT_LEN]> {
+    let pw = pw.to_string();
+    tokio::task::spawn_blocking(move || crate::crypto::hash_password(pw.as_bytes(), &salt, &params))
+        .await
+        .map_err(|e| AppError::E(format!("password hashing task failed: {e}")))?
+}
+
+/// Hash `pw` with a new salt and the currently preferred params
+async fn new_password_hash(pw: &str) -> Result<PasswordHash> {
+    let params = crate::crypto::preferred_password_params()?;
+    let salt = crate::crypto::new_pw_salt()?;
+    let hash = hash_password_blocking(pw, salt.clone(), params.clone()).await?;
+    Ok(PasswordHash {
+        salt: hex::encode(salt),
+        hash: hex::encode(hash),
+        params: Json(params),
+    })
+}
+
+async fn set_password(
+    executor: impl sqlx::PgExecutor<'_>,
+    user_id: i64,
+    pw: PasswordHash,
+) -> Result<()> {
+    sqlx::query(
+        r##"
+        update poop.users set pw_salt = $2, pw_hash = $3, pw_params = $4
+        where id = $1
+        "##,
+    )
+    .bind(user_id)
+    .bind(pw.salt)
+    .bind(pw.hash)
+    .bind(pw.params)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Check `pw` against the user's stored password hash
+async fn verify_password(user: &User, pw: &str) -> Result<bool> {
+    let user_hash = hex::decode(&user.pw_hash)?;
+    let this_hash =
+        hash_password_blocking(pw, hex::decode(&user.pw_salt)?, user.pw_params.0.clone()).await?;
+    Ok(ring::constant_time::verify_slices_are_equal(&user_hash, &this_hash).is_ok())
+}
+
+/// Re-hash the user's password if it was hashed with anything other than
+/// the currently preferred params. Must only be called with a verified `pw`.
+async fn upgrade_password_hash(pool: &PgPool, user: &User, pw: &str) -> Result<()> {
+    if *user.pw_params == crate::crypto::preferred_password_params()? {
+        return Ok(());
+    }
+    let new_pw = new_password_hash(pw).await?;
+    tracing::info!(
+        user_id = %user.id,
+        from = ?user.pw_params.0,
+        to = ?new_pw.params.0,
+        "upgrading password hash",
+    );
+    set_password(pool, user.id, new_pw).await
+}
+
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Create a login challenge for a user with 2FA enabled, returning its token
+async fn new_login_challenge(pool: &PgPool, user: &User) -> Result<String> {
+    let token = hex::encode(crate::crypto::rand_bytes(32)?);
+    let token_hash = crate::crypto::hmac_sign(&token);
+    let expires = Utc::now()
+        .checked_add_signed(chrono::Duration::seconds(
+            CONFIG.login_challenge_expiration_seconds as i64,
+        ))
+        .ok_or_else(|| AppError::from("error calculating challenge expiration"))?;
+    sqlx::query(
+        r##"
+        insert into poop.login_challenges
+            (user_id, hash, expires) values ($1, $2, $3)
+        "##,
+    )
+    .bind(user.id)
+    .bind(token_hash)
+    .bind(expires)
+    .execute(pool)
+    .await?;
+    Ok(token)
+}
+
+/// Drop the user's outstanding login challenges, they were started
+/// with a password that's no longer valid
+async fn revoke_login_challenges(executor: impl sqlx::PgExecutor<'_>, user_id: i64) -> Result<()> {
+    sqlx::query(
+        r##"
+        update poop.login_challenges set deleted = true
+        where user_id = $1
+            and deleted is false
+        "##,
+    )
+    .bind(user_id)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// The user's decrypted TOTP secret, if they've started setting up 2FA
+fn totp_secret(user: &User) -> Result<Option<Vec<u8>>> {
+    user.totp_secret
+        .as_ref()
+        .map(|enc| crate::crypto::base32_decode(&crate::crypto::decrypt(enc)?))
+        .transpose()
+}
+
+/// Recovery codes are shown grouped and may be typed in any case
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Replace the user's recovery codes, returning the new ones
+async fn new_recovery_codes(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i64,
+) -> Result<Vec<String>> {
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let code = hex::encode(crate::crypto::rand_bytes(5)?);
+        codes.push(format!("{}-{}", &code[..5], &code[5..]));
+    }
+    let hashes = codes
+        .iter()
+        .map(|c| crate::crypto::hmac_sign(&normalize_recovery_code(c)))
+        .collect::<Vec<_>>();
+
+    sqlx::query(
+        r##"
+        update poop.totp_recovery_codes set deleted = true
+        where user_id = $1
+            and deleted is false
+        "##,
+    )
+    .bind(user_id)
+    .execute(&mut *tr)
+    .await?;
+    sqlx::query(
+        r##"
+        insert into poop.totp_recovery_codes (user_id, hash)
+            select $1, hash from unnest($2) as hash
+        "##,
+    )
+    .bind(user_id)
+    .bind(&hashes)
+    .execute(&mut *tr)
+    .await?;
+    Ok(codes)
+}
+
+/// Check a TOTP code, or failing that a recovery code. Accepted codes are
+/// used up, a TOTP code can't be reused and neither can any older code.
+async fn verify_second_factor(pool: &PgPool, user: &User, code: &str) -> Result<bool> {
+    let secret = totp_secret(user)?.ok_or("2FA secret missing")?;
+    if let Some(step) = crate::crypto::totp_verify(&secret, code, Utc::now().timestamp()) {
+        let res = sqlx::query(
+            r##"
+            update poop.users set totp_last_step = $2
+            where id = $1
+                and (totp_last_step is null or totp_last_step < $2)
+            "##,
+        )
+        .bind(user.id)
+        .bind(step)
+        .execute(pool)
+        .await?;
+        return Ok(res.rows_affected() > 0);
+    }
+
+    let res = sqlx::query(
+        r##"
+        update poop.totp_recovery_codes set deleted = true
+        where id = (
+            select id from poop.totp_recovery_codes
+            where user_id = $1
+                and hash = $2
+                and deleted is false
+            limit 1
+        )
+        "##,
+    )
+    .bind(user.id)
+    .bind(crate::crypto::hmac_sign(&normalize_recovery_code(code)))
+    .execute(pool)
+    .await?;
+    if res.rows_affected() > 0 {
+        tracing::info!(user_id = %user.id, "used 2FA recovery code");
+    }
+    Ok(res.rows_affected() > 0)
+}
+
+/// Percent-encode everything but unreserved characters
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Delete all of the user's auth tokens, optionally sparing one
+async fn revoke_sessions(
+    executor: impl sqlx::PgExecutor<'_>,
+    user_id: i64,
+    except_token_id: Option<i64>,
+) -> Result<u64> {
+    let res = sqlx::query(
+        r##"
+        update poop.auth_tokens set deleted = true
+        where user_id = $1
+            and deleted is false
+            and ($2::bigint is null or id != $2)
+        "##,
+    )
+    .bind(user_id)
+    .bind(except_token_id)
+    .execute(executor)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+async fn send_password_reset(ctx: &Context<'_>, email: &str) -> Result<()> {
+    let pool = ctx.data_unchecked::<PgPool>();
+    let user: Option<User> =
+        sqlx::query_as("select * from poop.users where email = $1 and deleted is false")
+            .bind(email)
+            .fetch_optional(pool)
+            .await?;
+    let user = match user {
+        Some(user) => user,
+        None => {
+            tracing::info!(email = %email, "password reset requested for unknown email");
+            return Ok(());
+        }
+    };
+
+    let token = hex::encode(crate::crypto::rand_bytes(32)?);
+    let token_hash = crate::crypto::hmac_sign(&token);
+    let expires = Utc::now()
+        .checked_add_signed(chrono::Duration::seconds(
+            CONFIG.password_reset_expiration_seconds as i64,
+        ))
+        .ok_or_else(|| AppError::from("error calculating reset expiration"))?;
+    sqlx::query(
+        r##"
+        insert into poop.password_reset_tokens
+            (user_id, hash, expires) values ($1, $2, $3)
+        "##,
+    )
+    .bind(user.id)
+    .bind(token_hash)
+    .bind(expires)
+    .execute(pool)
+    .await?;
+
+    let mailer = ctx.data_unchecked::<AppMailer>();
+    mailer
+        .send(&Email {
+            to: user.email.clone(),
+            subject: "Reset your password".into(),
+            body: format!(
+                "Hi {name},\n\nUse the link below to reset your password. \
+                It expires in {minutes} minutes.\n\n{url}\n\n\
+                If you didn't ask for this, you can ignore this email.",
+                name = &user.name,
+                minutes = CONFIG.password_reset_expiration_seconds / 60,
+                url = CONFIG.get_reset_password_url(&token),
+            ),
+        })
+        .await?;
+    Ok(())
+}
+
+/// Mail a token to `email` that confirms it belongs to `user`
+async fn send_email_verification(ctx: &Context<'_>, user: &User, email: &str) -> Result<()> {
+    let pool = ctx.data_unchecked::<PgPool>();
+    let token = hex::encode(crate::crypto::rand_bytes(32)?);
+    let token_hash = crate::crypto::hmac_sign(&token);
+    let expires = Utc::now()
+        .checked_add_signed(chrono::Duration::seconds(
+            CONFIG.email_verification_expiration_seconds as i64,
+        ))
+        .ok_or_else(|| AppError::from("error calculating verification expiration"))?;
+    sqlx::query(
+        r##"
+        insert into poop.email_verifications
+            (user_id, email, hash, expires) values ($1, $2, $3, $4)
+        "##,
+    )
+    .bind(user.id)
+    .bind(email)
+    .bind(token_hash)
+    .bind(expires)
+    .execute(pool)
+    .await?;
+
+    let mailer = ctx.data_unchecked::<AppMailer>();
+    mailer
+        .send(&Email {
+            to: email.to_string(),
+            subject: "Verify your email".into(),
+            body: format!(
+                "Hi {name},\n\nUse the link below to verify your email address.\n\n{url}",
+                name = &user.name,
+                url = CONFIG.get_verify_email_url(&token),
+            ),
+        })
+        .await?;
+    Ok(())
+}
+
+/// A creature's access rows, locked until the end of the transaction
+struct LockedCreatureAccess {
+    access: Vec<(i64, CreatureAccessKind)>,
+}
+
+impl LockedCreatureAccess {
+    fn kind(&self, user_id: i64) -> Option<CreatureAccessKind> {
+        self.access
+            .iter()
+            .find(|(id, _)| *id == user_id)
+            .map(|(_, kind)| *kind)
+    }
+
+    fn creators(&self) -> usize {
+        self.access
+            .iter()
+            .filter(|(_, kind)| *kind == CreatureAccessKind::Creator)
+            .count()
+    }
+}
+
+/// Lock the creature's access rows so that roles can be checked and
+/// changed without racing other access changes. Temporary access that
+/// hasn't started yet or has expired is left out.
+async fn lock_creature_access(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    creature_id: i64,
+) -> Result<LockedCreatureAccess> {
+    #[derive(sqlx::FromRow)]
+    struct Access {
+        user_id: i64,
+        kind: String,
+    }
+    let access: Vec<Access> = sqlx::query_as(
+        r##"
+        select user_id, kind from poop.creature_access
+        where creature_id = $1
+            and deleted is false
+            and (starts is null or starts <= now())
+            and (expires is null or expires > now())
+        for update
+        "##,
+    )
+    .bind(creature_id)
+    .fetch_all(&mut *tr)
+    .await?;
+    Ok(LockedCreatureAccess {
+        access: access
+            .into_iter()
+            .map(|a| (a.user_id, CreatureAccessKind::from_db(&a.kind)))
+            .collect(),
+    })
+}
+
+/// Load the user's current role on a creature, holding their access row
+/// until the end of the transaction. The guards check roles through the
+/// loader, which may be stale by the time a mutation writes anything.
+async fn load_creature_role(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    creature_id: i64,
+    user_id: i64,
+) -> Result<Option<CreatureAccessKind>> {
+    #[derive(sqlx::FromRow)]
+    struct Access {
+        kind: String,
+    }
+    let access: Option<Access> = sqlx::query_as(
+        r##"
+        select ca.kind from poop.creature_access ca
+            inner join poop.creatures c on c.id = ca.creature_id
+        where ca.creature_id = $1
+            and ca.user_id = $2
+            and c.deleted is false
+            and ca.deleted is false
+            and (ca.starts is null or ca.starts <= now())
+            and (ca.expires is null or ca.expires > now())
+        for share of ca
+        "##,
+    )
+    .bind(creature_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tr)
+    .await?;
+    Ok(access.map(|a| CreatureAccessKind::from_db(&a.kind)))
+}
+
+/// Fail unless the user has at least `min` access to the creature, checked
+/// inside the transaction that's about to write
+async fn require_creature_role(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    creature_id: i64,
+    user_id: i64,
+    min: CreatureAccessKind,
+) -> Result<()> {
+    let role = load_creature_role(tr, creature_id, user_id).await?;
+    check_creature_role(role, min)
+}
+
+/// Drop any temporary access the user has to the creature, it's replaced
+/// by whatever is being granted
+async fn clear_temporary_access(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    creature_id: i64,
+    user_id: i64,
+) -> Result<()> {
+    sqlx::query(
+        r##"
+        update poop.creature_access set deleted = true
+        where creature_id = $1
+            and user_id = $2
+            and expires is not null
+            and deleted is false
+        "##,
+    )
+    .bind(creature_id)
+    .bind(user_id)
+    .execute(&mut *tr)
+    .await?;
+    Ok(())
+}
+
+/// An invite that was just accepted or declined
+#[derive(sqlx::FromRow)]
+struct ClosedInvite {
+    creature_id: i64,
+    creator_id: i64,
+    kind: String,
+}
+
+/// Mark the user's pending invite, looked up by either `id` or `token`,
+/// as accepted or declined
+async fn close_invite(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i64,
+    id: Option<i64>,
+    token: Option<String>,
+    accept: bool,
+) -> Result<ClosedInvite> {
+    if id.is_none() && token.is_none() {
+        return Err(AppError::BadRequest("invite id or token required".into()));
+    }
+    let token_hash = token.map(|t| crate::crypto::hmac_sign(&t));
+    let invite: Option<ClosedInvite> = sqlx::query_as(
+        r##"
+        update poop.creature_invites set
+            deleted = true,
+            accepted_at = case when $4 then now() end,
+            declined_at = case when $4 then null else now() end
+        where user_id = $1
+            and ($2::bigint is null or id = $2)
+            and ($3::text is null or hash = $3)
+            and deleted is false
+            and expires > now()
+        returning creature_id, creator_id, kind
+        "##,
+    )
+    .bind(user_id)
+    .bind(id)
+    .bind(token_hash)
+    .bind(accept)
+    .fetch_optional(&mut *tr)
+    .await?;
+    invite.ok_or_else(|| AppError::BadRequest("invalid or expired invite".into()))
+}
+
+/// Unverified users can only have one live creature. The user's row is
+/// locked so concurrent creates and restores can't both get through.
+async fn check_unverified_creature_limit(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user: &User,
+) -> FieldResult<()> {
+    if user.verified_at.is_some() {
+        return Ok(());
+    }
+    sqlx::query("select id from poop.users where id = $1 for update")
+        .bind(user.id)
+        .execute(&mut *tr)
+        .await?;
+    #[derive(sqlx::FromRow)]
+    struct Count {
+        count: i64,
+    }
+    let created: Count = sqlx::query_as(
+        r##"
+        select count(*) as count from poop.creatures
+        where creator_id = $1
+            and deleted is false
+        "##,
+    )
+    .bind(user.id)
+    .fetch_one(&mut *tr)
+    .await?;
+    if created.count >= 1 {
+        return Err(
+            AppError::Forbidden("verify your email to create more creatures".into())
+                .extend_with(|_, ex| ex.set("key", "EMAIL_NOT_VERIFIED")),
+        );
+    }
+    Ok(())
+}
+
+/// The creature as seen by `user_id`
+async fn load_creature(
+    executor: impl sqlx::PgExecutor<'_>,
+    id: i64,
+    user_id: i64,
+) -> Result<CreatureRelation> {
+    let c: CreatureRelation = sqlx::query_as(
+        r##"
+        select c.*, ca.user_id, ca.kind from poop.creatures c
+            inner join poop.creature_access ca on ca.creature_id = c.id
+        where c.id = $1
+            and ca.user_id = $2
+            and c.deleted is false
+            and ca.deleted is false
+            and (ca.starts is null or ca.starts <= now())
+            and (ca.expires is null or ca.expires > now())
+        "##,
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(c)
+}
+
+/// Validate `profile` and write it to the creature
+async fn save_creature_profile(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    id: i64,
+    user_id: i64,
+    profile: CreatureProfileInput,
+) -> FieldResult<CreatureRelation> {
+    profile.validate().map_err(|e| e.extend())?;
+    if let async_graphql::MaybeUndefined::Value(species) = &profile.species {
+        let known: Option<Species> =
+            sqlx::query_as("select * from poop.species where species = $1")
+                .bind(species)
+                .fetch_optional(&mut *tr)
+                .await?;
+        if known.is_none() {
+            return Err(AppError::BadRequest(format!("unknown species {species}"))
+                .extend_with(|_, ex| ex.set("key", "UNKNOWN_SPECIES")));
+        }
+    }
+    let mut c = load_creature(&mut *tr, id, user_id).await?;
+    profile.apply(&mut c);
+    sqlx::query(
+        r##"
+        update poop.creatures set
+            species = $2,
+            breed = $3,
+            birthdate = $4,
+            sex = $5,
+            initial_weight_grams = $6,
+            photo_url = $7
+        where id = $1
+        "##,
+    )
+    .bind(id)
+    .bind(&c.species)
+    .bind(&c.breed)
+    .bind(c.birthdate)
+    .bind(&c.sex)
+    .bind(c.initial_weight_grams)
+    .bind(&c.photo_url)
+    .execute(&mut *tr)
+    .await?;
+    Ok(load_creature(&mut *tr, id, user_id).await?)
+}
+
+/// Poops are stored as `poop` events and read through the `poop.poops` view
+async fn load_poop(executor: impl sqlx::PgExecutor<'_>, id: i64) -> Result<Poop> {
+    let p: Poop = sqlx::query_as("select * from poop.poops where id = $1")
+        .bind(id)
+        .fetch_one(executor)
+        .await?;
+    Ok(p)
+}
+
+/// Write the poop's editable fields
+async fn save_poop(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>, p: &Poop) -> Result<Poop> {
+    sqlx::query(
+        r##"
+        update poop.events set
+            occurred_at = $2,
+            attrs = jsonb_strip_nulls(jsonb_build_object(
+                'bristol', $3::smallint,
+                'color', $4::text,
+                'size', $5::text,
+                'blood', $6::boolean,
+                'mucus', $7::boolean
+            )),
+            notes = $8
+        where id = $1
+            and kind = 'poop'
+        "##,
+    )
+    .bind(p.id)
+    .bind(p.occurred_at)
+    .bind(p.bristol)
+    .bind(&p.color)
+    .bind(&p.size)
+    .bind(p.blood)
+    .bind(p.mucus)
+    .bind(&p.notes)
+    .execute(&mut *tr)
+    .await?;
+    load_poop(&mut *tr, p.id).await
+}
+
+/// A weight along with the growth since the creature's previous one
+async fn load_weight(executor: impl sqlx::PgExecutor<'_>, id: i64) -> Result<Weight> {
+    let w: Weight = sqlx::query_as(
+        r##"
+        select * from (
+            select w.*,
+                ((w.grams - lag(w.grams) over win)
+                    / nullif(extract(epoch from w.measured_at - lag(w.measured_at) over win), 0)
+                    * 86400)::double precision as growth_grams_per_day
+            from poop.weights w
+            where w.creature_id = (select creature_id from poop.weights where id = $1)
+                and w.deleted is false
+            window win as (order by w.measured_at, w.id)
+        ) w
+        where w.id = $1
+        "##,
+    )
+    .bind(id)
+    .fetch_one(executor)
+    .await?;
+    Ok(w)
+}
+
+/// Load and lock a poop the user is allowed to change: one they logged
+/// and can still log for, or any poop of a creature they created. Checked
+/// inside the transaction that changes it so revoked access can't slip by.
+async fn editable_poop(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user: &User,
+    id: i64,
+    deleted: bool,
+) -> FieldResult<Poop> {
+    let poop: Option<Poop> =
+        sqlx::query_as("select * from poop.poops where id = $1 and deleted = $2 for update")
+            .bind(id)
+            .bind(deleted)
+            .fetch_optional(&mut *tr)
+            .await?;
+    if let Some(poop) = poop {
+        let kind = load_creature_role(tr, poop.creature_id, user.id).await?;
+        let allowed = match kind {
+            Some(CreatureAccessKind::Creator) => true,
+            Some(CreatureAccessKind::Pooper) => poop.creator_id == user.id,
+            _ => false,
+        };
+        if allowed {
+            return Ok(poop);
+        }
+    }
+    Err(AppError::Forbidden(
+        "only the poop's creator or the creature's creator can change it".into(),
+    )
+    .extend())
+}
+
+/// Overwrite the auth cookie with a junk token
+fn logout_ctx(ctx: &Context<'_>) {
+    let token = hex::encode(crate::crypto::rand_bytes(31).unwrap_or_else(|_| vec![0; 31]));
+    let token = format!("xx{token}");
+    let cookie_str = format_set_cookie(&token, CONFIG.auth_expiration_seconds as i64);
+    ctx.insert_http_header("set-cookie", cookie_str);
+}
+
 pub struct MutationRoot;
 
 #[Object]
@@ -73,169 +868,1433 @@ impl MutationRoot {
         name: String,
         pw: String,
     ) -> FieldResult<User> {
-        let salt = crate::crypto::new_pw_salt().expect("error generating salt");
-        let hash = crate::crypto::derive_password_hash(pw.as_bytes(), salt.as_ref());
-        let salt = hex::encode(salt);
-        let hash = hex::encode(hash);
+        let pw = new_password_hash(&pw).await?;
         let pool = ctx.data_unchecked::<PgPool>();
 
         let user = sqlx::query_as(
             r##"
-            insert into poop.users (name, email, pw_salt, pw_hash)
-                values ($1, $2, $3, $4)
+            insert into poop.users (name, email, pw_salt, pw_hash, pw_params)
+                values ($1, $2, $3, $4, $5)
                 returning *
         "##,
         )
         .bind(name)
         .bind(email)
-        .bind(salt)
-        .bind(hash)
+        .bind(pw.salt)
+        .bind(pw.hash)
+        .bind(pw.params)
         .fetch_one(pool)
         .await
         .map_err(AppError::from)
         .extend_err(|_e, ex| ex.set("key", "INVALID_USER_SIGN_UP"))?;
 
         login_ctx(ctx, &user).await?;
-        Ok(user)
-    }
-
-    async fn login(&self, ctx: &Context<'_>, email: String, pw: String) -> FieldResult<User> {
-        let pool = ctx.data_unchecked::<PgPool>();
-        let user: User =
-            sqlx::query_as("select * from poop.users where email = $1 and deleted is false")
-                .bind(email)
-                .fetch_one(pool)
-                .await
-                .map_err(AppError::from)
-                .map_err(|e| {
-                    if e.is_db_not_found() {
-                        AppError::BadRequest("bad request".into())
-                    } else {
-                        e
-                    }
-                })?;
-        let user_hash = hex::decode(&user.pw_hash)?;
-        let this_hash = crate::crypto::derive_password_hash(
-            pw.as_bytes(),
-            hex::decode(&user.pw_salt)?.as_ref(),
-        );
-        if ring::constant_time::verify_slices_are_equal(&user_hash, &this_hash).is_err() {
-            return Err(AppError::BadRequest("bad request".into()).extend());
+        if let Err(e) = send_email_verification(ctx, &user, &user.email).await {
+            tracing::error!(error = ?e, user_id = %user.id, "error sending email verification");
         }
-        login_ctx(ctx, &user).await?;
         Ok(user)
     }
 
-    async fn logout(&self, ctx: &Context<'_>) -> bool {
-        let token = hex::encode(crate::crypto::rand_bytes(31).unwrap_or_else(|_| vec![0; 31]));
-        let token = format!("xx{token}");
-        let cookie_str = format_set_cookie(&token);
-        ctx.insert_http_header("set-cookie", cookie_str);
-        true
+    /// Send a fresh verification email to the current user's unverified address
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn resend_email_verification(&self, ctx: &Context<'_>) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        if user.verified_at.is_some() {
+            return Err(AppError::BadRequest("email is already verified".into()).extend());
+        }
+        send_email_verification(ctx, user, &user.email).await?;
+        Ok(true)
     }
 
-    #[graphql(guard = "LoginGuard::new()")]
-    async fn create_creature(
+    /// Log in with an email and password. When the user has 2FA enabled,
+    /// no session is started and a challenge is returned instead that
+    /// has to be completed with `completeLogin`.
+    async fn login(
         &self,
         ctx: &Context<'_>,
-        name: String,
-    ) -> FieldResult<CreatureRelation> {
-        let user = ctx.data_unchecked::<User>();
+        email: String,
+        pw: String,
+    ) -> FieldResult<LoginResult> {
         let pool = ctx.data_unchecked::<PgPool>();
-        #[derive(sqlx::FromRow)]
-        struct CId {
-            id: i64,
+        let throttle = ctx.data_unchecked::<Throttle>();
+        let meta = ctx.data_opt::<RequestMeta>().cloned().unwrap_or_default();
+        let mut attempt_keys = vec![(AttemptKind::Email, email.as_str())];
+        if let Some(ip) = meta.ip.as_deref() {
+            attempt_keys.push((AttemptKind::Ip, ip));
         }
+        throttle
+            .attempt(&attempt_keys)
+            .await
+            .map_err(|e| e.extend())?;
 
-        let mut tr = pool.begin().await?;
-        let c_id: CId = sqlx::query_as(
+        let user: Option<User> =
+            sqlx::query_as("select * from poop.users where email = $1 and deleted is false")
+                .bind(&email)
+                .fetch_optional(pool)
+                .await?;
+        let verified = match &user {
+            Some(user) => verify_password(user, &pw).await?,
+            None => false,
+        };
+        let user = match user {
+            Some(user) if verified => user,
+            _ => {
+                tracing::info!(email = %email, "failed login");
+                return Err(AppError::BadRequest("bad request".into()).extend());
+            }
+        };
+        throttle.refund(&attempt_keys).await?;
+        if let Err(e) = upgrade_password_hash(pool, &user, &pw).await {
+            tracing::error!(error = ?e, user_id = %user.id, "error upgrading password hash");
+        }
+        if user.totp_enabled_at.is_some() {
+            let challenge = new_login_challenge(pool, &user).await?;
+            return Ok(LoginResult {
+                user: None,
+                challenge: Some(challenge),
+            });
+        }
+        // the ip counter is left to age out so that logging in to one account
+        // doesn't clear failures against others
+        throttle
+            .reset(&[(AttemptKind::Email, email.as_str())])
+            .await?;
+        login_ctx(ctx, &user).await?;
+        Ok(LoginResult {
+            user: Some(user),
+            challenge: None,
+        })
+    }
+
+    /// Finish a 2FA login with a code from the user's
+    /// authenticator app or one of their recovery codes
+    async fn complete_login(
+        &self,
+        ctx: &Context<'_>,
+        challenge: String,
+        code: String,
+    ) -> FieldResult<User> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let throttle = ctx.data_unchecked::<Throttle>();
+        let meta = ctx.data_opt::<RequestMeta>().cloned().unwrap_or_default();
+        let challenge_hash = crate::crypto::hmac_sign(&challenge);
+        let user: User = sqlx::query_as(
+            r##"
+            select u.* from poop.users u
+                inner join poop.login_challenges lc on lc.user_id = u.id
+            where lc.hash = $1
+                and lc.deleted is false
+                and lc.expires > now()
+                and u.deleted is false
+            "##,
+        )
+        .bind(&challenge_hash)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| {
+            AppError::BadRequest("invalid or expired login challenge".into()).extend()
+        })?;
+
+        let mut attempt_keys = vec![(AttemptKind::Email, user.email.as_str())];
+        if let Some(ip) = meta.ip.as_deref() {
+            attempt_keys.push((AttemptKind::Ip, ip));
+        }
+        throttle
+            .attempt(&attempt_keys)
+            .await
+            .map_err(|e| e.extend())?;
+        if !verify_second_factor(pool, &user, &code).await? {
+            tracing::info!(user_id = %user.id, "failed login code");
+            return Err(AppError::BadRequest("invalid code".into()).extend());
+        }
+        throttle.refund(&attempt_keys).await?;
+
+        let res = sqlx::query(
+            r##"
+            update poop.login_challenges set deleted = true
+            where hash = $1
+                and deleted is false
+            "##,
+        )
+        .bind(&challenge_hash)
+        .execute(pool)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::BadRequest("invalid or expired login challenge".into()).extend());
+        }
+        throttle
+            .reset(&[(AttemptKind::Email, user.email.as_str())])
+            .await?;
+        login_ctx(ctx, &user).await?;
+        Ok(user)
+    }
+
+    /// Start setting up 2FA. It isn't enabled until a code
+    /// from the new secret is given to `confirmTotp`.
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn enable_totp(&self, ctx: &Context<'_>) -> FieldResult<TotpSetup> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        if user.totp_enabled_at.is_some() {
+            return Err(AppError::BadRequest("2FA is already enabled".into()).extend());
+        }
+        let secret = crate::crypto::base32_encode(&crate::crypto::rand_bytes(20)?);
+        let enc = crate::crypto::encrypt(&secret)?;
+        sqlx::query(
+            r##"
+            update poop.users set totp_secret = $2, totp_last_step = null
+            where id = $1
+            "##,
+        )
+        .bind(user.id)
+        .bind(Json(enc))
+        .execute(pool)
+        .await?;
+
+        let uri = format!(
+            "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}\
+            &algorithm=SHA1&digits={digits}&period={period}",
+            issuer = percent_encode(&CONFIG.totp_issuer),
+            account = percent_encode(&user.email),
+            secret = &secret,
+            digits = crate::crypto::TOTP_DIGITS,
+            period = crate::crypto::TOTP_STEP_SECONDS,
+        );
+        Ok(TotpSetup { secret, uri })
+    }
+
+    /// Turn on 2FA with a code from the secret returned by `enableTotp`.
+    /// Returns one-time recovery codes, which are only ever shown here.
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn confirm_totp(&self, ctx: &Context<'_>, code: String) -> FieldResult<Vec<String>> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        if user.totp_enabled_at.is_some() {
+            return Err(AppError::BadRequest("2FA is already enabled".into()).extend());
+        }
+        let secret = totp_secret(user)?
+            .ok_or_else(|| AppError::BadRequest("2FA setup hasn't been started".into()).extend())?;
+        let step = crate::crypto::totp_verify(&secret, &code, Utc::now().timestamp())
+            .ok_or_else(|| AppError::BadRequest("invalid code".into()).extend())?;
+
+        let mut tr = pool.begin().await?;
+        sqlx::query(
+            r##"
+            update poop.users set totp_enabled_at = now(), totp_last_step = $2
+            where id = $1
+            "##,
+        )
+        .bind(user.id)
+        .bind(step)
+        .execute(&mut tr)
+        .await?;
+        let codes = new_recovery_codes(&mut tr, user.id).await?;
+        tr.commit().await?;
+        Ok(codes)
+    }
+
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn disable_totp(&self, ctx: &Context<'_>, pw: String) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        if !verify_password(user, &pw).await? {
+            return Err(AppError::BadRequest("incorrect password".into()).extend());
+        }
+
+        let mut tr = pool.begin().await?;
+        sqlx::query(
+            r##"
+            update poop.users set
+                totp_secret = null,
+                totp_enabled_at = null,
+                totp_last_step = null
+            where id = $1
+            "##,
+        )
+        .bind(user.id)
+        .execute(&mut tr)
+        .await?;
+        sqlx::query(
+            r##"
+            update poop.totp_recovery_codes set deleted = true
+            where user_id = $1
+                and deleted is false
+            "##,
+        )
+        .bind(user.id)
+        .execute(&mut tr)
+        .await?;
+        tr.commit().await?;
+        Ok(true)
+    }
+
+    async fn logout(&self, ctx: &Context<'_>) -> FieldResult<bool> {
+        if let Some(token) = ctx.data_opt::<AuthToken>() {
+            let pool = ctx.data_unchecked::<PgPool>();
+            sqlx::query(
+                r##"
+                update poop.auth_tokens set deleted = true
+                where id = $1
+                "##,
+            )
+            .bind(token.id)
+            .execute(pool)
+            .await?;
+        }
+        logout_ctx(ctx);
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn logout_everywhere(&self, ctx: &Context<'_>) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        revoke_sessions(pool, user.id, None).await?;
+        logout_ctx(ctx);
+        Ok(true)
+    }
+
+    /// Email a password reset link. Always succeeds so that it can't
+    /// be used to discover which emails have accounts.
+    async fn request_password_reset(&self, ctx: &Context<'_>, email: String) -> bool {
+        if let Err(e) = send_password_reset(ctx, &email).await {
+            tracing::error!(error = ?e, "error sending password reset");
+        }
+        true
+    }
+
+    async fn reset_password(
+        &self,
+        ctx: &Context<'_>,
+        token: String,
+        new_pw: String,
+    ) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let token_hash = crate::crypto::hmac_sign(&token);
+        #[derive(sqlx::FromRow)]
+        struct UId {
+            user_id: i64,
+        }
+
+        let mut tr = pool.begin().await?;
+        let u_id: UId = sqlx::query_as(
+            r##"
+            update poop.password_reset_tokens set deleted = true
+            where hash = $1
+                and deleted is false
+                and expires > now()
+            returning user_id
+            "##,
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut tr)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("invalid or expired reset token".into()).extend())?;
+
+        set_password(&mut tr, u_id.user_id, new_password_hash(&new_pw).await?).await?;
+
+        // any other outstanding reset tokens are dead too
+        sqlx::query(
+            r##"
+            update poop.password_reset_tokens set deleted = true
+            where user_id = $1
+                and deleted is false
+            "##,
+        )
+        .bind(u_id.user_id)
+        .execute(&mut tr)
+        .await?;
+
+        revoke_sessions(&mut tr, u_id.user_id, None).await?;
+        revoke_login_challenges(&mut tr, u_id.user_id).await?;
+        tr.commit().await?;
+        Ok(true)
+    }
+
+    /// Change the current user's password, logging out all of their other sessions
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn change_password(
+        &self,
+        ctx: &Context<'_>,
+        old_pw: String,
+        new_pw: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        if !verify_password(user, &old_pw).await? {
+            return Err(AppError::BadRequest("incorrect password".into()).extend());
+        }
+        let new_pw = new_password_hash(&new_pw).await?;
+
+        let mut tr = pool.begin().await?;
+        set_password(&mut tr, user.id, new_pw).await?;
+        let current_token_id = ctx.data_opt::<AuthToken>().map(|t| t.id);
+        revoke_sessions(&mut tr, user.id, current_token_id).await?;
+        revoke_login_challenges(&mut tr, user.id).await?;
+        tr.commit().await?;
+        Ok(true)
+    }
+
+    /// Start changing the current user's email. The change is applied
+    /// once the new address is confirmed with `verifyEmail`.
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn change_email(
+        &self,
+        ctx: &Context<'_>,
+        new_email: String,
+        pw: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        if !verify_password(user, &pw).await? {
+            return Err(AppError::BadRequest("incorrect password".into()).extend());
+        }
+
+        #[derive(sqlx::FromRow)]
+        struct Taken {
+            taken: bool,
+        }
+        let taken: Taken =
+            sqlx::query_as("select exists(select 1 from poop.users where email = $1) as taken")
+                .bind(&new_email)
+                .fetch_one(pool)
+                .await?;
+        if taken.taken {
+            return Err(AppError::BadRequest("email is already in use".into())
+                .extend_with(|_, ex| ex.set("key", "EMAIL_TAKEN")));
+        }
+
+        send_email_verification(ctx, user, &new_email).await?;
+        Ok(true)
+    }
+
+    /// Confirm an email address using the token that was mailed to it
+    async fn verify_email(&self, ctx: &Context<'_>, token: String) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let token_hash = crate::crypto::hmac_sign(&token);
+        #[derive(sqlx::FromRow)]
+        struct Verification {
+            user_id: i64,
+            email: String,
+        }
+
+        let mut tr = pool.begin().await?;
+        let v: Verification = sqlx::query_as(
+            r##"
+            update poop.email_verifications set deleted = true
+            where hash = $1
+                and deleted is false
+                and expires > now()
+            returning user_id, email
+            "##,
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut tr)
+        .await?
+        .ok_or_else(|| {
+            AppError::BadRequest("invalid or expired verification token".into()).extend()
+        })?;
+
+        sqlx::query(
+            r##"
+            update poop.users set email = $2, verified_at = now()
+            where id = $1
+                and deleted is false
+            "##,
+        )
+        .bind(v.user_id)
+        .bind(v.email)
+        .execute(&mut tr)
+        .await
+        .map_err(AppError::from)
+        .extend_err(|_e, ex| ex.set("key", "EMAIL_TAKEN"))?;
+
+        // older links would otherwise switch the email back
+        sqlx::query(
+            r##"
+            update poop.email_verifications set deleted = true
+            where user_id = $1
+                and deleted is false
+            "##,
+        )
+        .bind(v.user_id)
+        .execute(&mut tr)
+        .await?;
+        tr.commit().await?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn revoke_session(&self, ctx: &Context<'_>, id: String) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.parse::<i64>()?;
+        let res = sqlx::query(
+            r##"
+            update poop.auth_tokens set deleted = true
+            where id = $1
+                and user_id = $2
+                and deleted is false
+            "##,
+        )
+        .bind(id)
+        .bind(user.id)
+        .execute(pool)
+        .await?;
+        if ctx.data_opt::<AuthToken>().map(|t| t.id) == Some(id) {
+            logout_ctx(ctx);
+        }
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Create a personal api token. The raw token is only returned here,
+    /// only its hash is stored.
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn create_api_token(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        scope: ApiTokenScope,
+        expires: Option<chrono::DateTime<Utc>>,
+    ) -> FieldResult<CreatedApiToken> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::BadRequest("name required".into()).extend());
+        }
+        if expires.map(|e| e <= Utc::now()).unwrap_or(false) {
+            return Err(AppError::BadRequest("expiration must be in the future".into()).extend());
+        }
+        let token = format!("dp_{}", hex::encode(crate::crypto::rand_bytes(32)?));
+        let token_hash = crate::crypto::hmac_sign(&token);
+        let api_token: ApiToken = sqlx::query_as(
+            r##"
+            insert into poop.api_tokens
+                (user_id, name, hash, scope, expires) values ($1, $2, $3, $4, $5)
+            returning *
+            "##,
+        )
+        .bind(user.id)
+        .bind(name)
+        .bind(token_hash)
+        .bind(scope.as_str())
+        .bind(expires)
+        .fetch_one(pool)
+        .await?;
+        Ok(CreatedApiToken { token, api_token })
+    }
+
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn revoke_api_token(&self, ctx: &Context<'_>, id: String) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.parse::<i64>()?;
+        let res = sqlx::query(
+            r##"
+            update poop.api_tokens set deleted = true
+            where id = $1
+                and user_id = $2
+                and deleted is false
+            "##,
+        )
+        .bind(id)
+        .bind(user.id)
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    #[graphql(guard = "LoginGuard::write()")]
+    async fn create_creature(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        profile: Option<CreatureProfileInput>,
+    ) -> FieldResult<CreatureRelation> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        #[derive(sqlx::FromRow)]
+        struct CId {
+            id: i64,
+        }
+
+        let mut tr = pool.begin().await?;
+        check_unverified_creature_limit(&mut tr, user).await?;
+        let c_id: CId = sqlx::query_as(
             "insert into poop.creatures (creator_id, name) values ($1, $2) returning id",
         )
-        .bind(&user.id)
-        .bind(&name)
+        .bind(user.id)
+        .bind(name)
+        .fetch_one(&mut tr)
+        .await?;
+
+        sqlx::query(
+            r##"
+            insert into poop.creature_access
+                (creature_id, user_id, creator_id, kind) values
+                ($1, $2, $3, $4)
+            "##,
+        )
+        .bind(c_id.id)
+        .bind(user.id)
+        .bind(user.id)
+        .bind("creator")
+        .execute(&mut tr)
+        .await?;
+
+        let c = match profile {
+            Some(profile) => save_creature_profile(&mut tr, c_id.id, user.id, profile).await?,
+            None => load_creature(&mut tr, c_id.id, user.id).await?,
+        };
+        tr.commit().await?;
+        Ok(c)
+    }
+
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&id, CreatureAccessKind::Creator))"
+    )]
+    async fn update_creature(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        name: Option<String>,
+    ) -> FieldResult<CreatureRelation> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.parse::<i64>()?;
+        let name = name.map(|n| n.trim().to_string());
+        if name.as_deref().map(str::is_empty).unwrap_or(false) {
+            return Err(AppError::BadRequest("name can't be empty".into()).extend());
+        }
+
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, id, user.id, CreatureAccessKind::Creator)
+            .await
+            .map_err(|e| e.extend())?;
+        sqlx::query(
+            r##"
+            update poop.creatures set name = coalesce($2, name)
+            where id = $1
+                and deleted is false
+            "##,
+        )
+        .bind(id)
+        .bind(name)
+        .execute(&mut tr)
+        .await?;
+        let c = load_creature(&mut tr, id, user.id).await?;
+        tr.commit().await?;
+        Ok(c)
+    }
+
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&id, CreatureAccessKind::Creator))"
+    )]
+    async fn update_creature_profile(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        profile: CreatureProfileInput,
+    ) -> FieldResult<CreatureRelation> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.parse::<i64>()?;
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, id, user.id, CreatureAccessKind::Creator)
+            .await
+            .map_err(|e| e.extend())?;
+        let c = save_creature_profile(&mut tr, id, user.id, profile).await?;
+        tr.commit().await?;
+        Ok(c)
+    }
+
+    /// Soft-delete a creature. It can be restored with `restoreCreature`
+    /// for `creature_restore_grace_seconds`.
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&id, CreatureAccessKind::Creator))"
+    )]
+    async fn delete_creature(&self, ctx: &Context<'_>, id: String) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.parse::<i64>()?;
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, id, user.id, CreatureAccessKind::Creator)
+            .await
+            .map_err(|e| e.extend())?;
+        let res = sqlx::query(
+            r##"
+            update poop.creatures set deleted = true, deleted_at = now()
+            where id = $1
+                and deleted is false
+            "##,
+        )
+        .bind(id)
+        .execute(&mut tr)
+        .await?;
+        tr.commit().await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Restore a deleted creature. The creature guard only sees live
+    /// creatures, so access is checked here.
+    #[graphql(guard = "LoginGuard::write()")]
+    async fn restore_creature(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> FieldResult<CreatureRelation> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.parse::<i64>()?;
+
+        #[derive(sqlx::FromRow)]
+        struct Deleted {
+            kind: String,
+            deleted_at: Option<chrono::DateTime<Utc>>,
+        }
+        let mut tr = pool.begin().await?;
+        let deleted: Option<Deleted> = sqlx::query_as(
+            r##"
+            select ca.kind, c.deleted_at from poop.creatures c
+                inner join poop.creature_access ca on ca.creature_id = c.id
+            where c.id = $1
+                and ca.user_id = $2
+                and ca.deleted is false
+            for update of c
+            "##,
+        )
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(&mut tr)
+        .await?;
+        let deleted_at = match deleted {
+            Some(d) if CreatureAccessKind::from_db(&d.kind) == CreatureAccessKind::Creator => {
+                d.deleted_at
+            }
+            _ => {
+                return Err(
+                    AppError::Forbidden("creator access to creature required".into()).extend(),
+                )
+            }
+        };
+        let deleted_at = deleted_at
+            .ok_or_else(|| AppError::BadRequest("creature isn't deleted".into()).extend())?;
+        let grace = chrono::Duration::seconds(CONFIG.creature_restore_grace_seconds as i64);
+        if deleted_at + grace < Utc::now() {
+            return Err(AppError::BadRequest(
+                "creature was deleted too long ago to restore".into(),
+            )
+            .extend_with(|_, ex| ex.set("key", "RESTORE_EXPIRED")));
+        }
+        check_unverified_creature_limit(&mut tr, user).await?;
+
+        sqlx::query(
+            r##"
+            update poop.creatures set deleted = false, deleted_at = null
+            where id = $1
+            "##,
+        )
+        .bind(id)
+        .execute(&mut tr)
+        .await?;
+        let c: CreatureRelation = sqlx::query_as(
+            r##"
+            select c.*, ca.user_id, ca.kind from poop.creatures c
+                inner join poop.creature_access ca on ca.creature_id = c.id
+            where c.id = $1
+                and ca.user_id = $2
+                and c.deleted is false
+                and ca.deleted is false
+            "##,
+        )
+        .bind(id)
+        .bind(user.id)
+        .fetch_one(&mut tr)
+        .await?;
+        tr.commit().await?;
+        Ok(c)
+    }
+
+    /// Invite another verified user to a creature. Only the creature's
+    /// creators can invite. Succeeds whether or not the email belongs to a
+    /// verified user so that it can't be used to discover accounts, the
+    /// invite is only made and emailed when it does.
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&creature_id, CreatureAccessKind::Creator))"
+    )]
+    async fn invite_to_creature(
+        &self,
+        ctx: &Context<'_>,
+        creature_id: String,
+        email: String,
+        kind: CreatureAccessKind,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let creature_id = creature_id.parse::<i64>()?;
+
+        let creature = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(CreatureUserId(creature_id, user.id))
+            .await?
+            .ok_or_else(|| AppError::E(format!("missing creature {creature_id}")).extend())?;
+
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, creature_id, user.id, CreatureAccessKind::Creator)
+            .await
+            .map_err(|e| e.extend())?;
+
+        let invitee: Option<User> =
+            sqlx::query_as("select * from poop.users where email = $1 and deleted is false")
+                .bind(email.trim())
+                .fetch_optional(&mut tr)
+                .await?;
+        let invitee = match invitee {
+            Some(invitee) if invitee.verified_at.is_some() => invitee,
+            _ => return Ok(true),
+        };
+
+        #[derive(sqlx::FromRow)]
+        struct Member {
+            member: bool,
+        }
+        let member: Member = sqlx::query_as(
+            r##"
+            select exists(
+                select 1 from poop.creature_access
+                where creature_id = $1
+                    and user_id = $2
+                    and expires is null
+                    and deleted is false
+            ) as member
+            "##,
+        )
+        .bind(creature_id)
+        .bind(invitee.id)
         .fetch_one(&mut tr)
         .await?;
+        if member.member {
+            return Err(AppError::BadRequest("user already has access".into())
+                .extend_with(|_, ex| ex.set("key", "ALREADY_MEMBER")));
+        }
 
+        // a new invite replaces any outstanding one
         sqlx::query(
+            r##"
+            update poop.creature_invites set deleted = true
+            where creature_id = $1
+                and user_id = $2
+                and deleted is false
+            "##,
+        )
+        .bind(creature_id)
+        .bind(invitee.id)
+        .execute(&mut tr)
+        .await?;
+
+        let token = hex::encode(crate::crypto::rand_bytes(32)?);
+        let token_hash = crate::crypto::hmac_sign(&token);
+        let expires = Utc::now()
+            .checked_add_signed(chrono::Duration::seconds(
+                CONFIG.creature_invite_expiration_seconds as i64,
+            ))
+            .ok_or_else(|| AppError::from("error calculating invite expiration"))?;
+        let invite: CreatureInvite = sqlx::query_as(
+            r##"
+            insert into poop.creature_invites
+                (creature_id, user_id, creator_id, kind, hash, expires) values
+                ($1, $2, $3, $4, $5, $6)
+            returning *, $7::text as creature_name
+            "##,
+        )
+        .bind(creature_id)
+        .bind(invitee.id)
+        .bind(user.id)
+        .bind(kind.as_str())
+        .bind(token_hash)
+        .bind(expires)
+        .bind(&creature.name)
+        .fetch_one(&mut tr)
+        .await?;
+        tr.commit().await?;
+
+        let mailer = ctx.data_unchecked::<AppMailer>();
+        let email = Email {
+            to: invitee.email.clone(),
+            subject: format!("You've been invited to {}", creature.name),
+            body: format!(
+                "Hi {name},\n\n{inviter} invited you to help look after {creature}. \
+                 Use the link below to accept.\n\n{url}",
+                name = &invitee.name,
+                inviter = &user.name,
+                creature = &creature.name,
+                url = CONFIG.get_accept_invite_url(&token),
+            ),
+        };
+        if let Err(e) = mailer.send(&email).await {
+            tracing::error!(error = ?e, invite_id = %invite.id, "error sending creature invite");
+        }
+        Ok(true)
+    }
+
+    /// Accept a pending invite by its id or the token from the invite email
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn accept_invite(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<String>,
+        token: Option<String>,
+    ) -> FieldResult<CreatureRelation> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.map(|id| id.parse::<i64>()).transpose()?;
+
+        let mut tr = pool.begin().await?;
+        let invite = close_invite(&mut tr, user.id, id, token, true)
+            .await
+            .map_err(|e| e.extend())?;
+        clear_temporary_access(&mut tr, invite.creature_id, user.id).await?;
+        let res = sqlx::query(
             r##"
             insert into poop.creature_access
                 (creature_id, user_id, creator_id, kind) values
                 ($1, $2, $3, $4)
+            on conflict (creature_id, user_id) where deleted is false do nothing
             "##,
         )
-        .bind(&c_id.id)
-        .bind(&user.id)
-        .bind(&user.id)
-        .bind("creator")
+        .bind(invite.creature_id)
+        .bind(user.id)
+        .bind(invite.creator_id)
+        .bind(&invite.kind)
         .execute(&mut tr)
         .await?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::BadRequest("you already have access".into())
+                .extend_with(|_, ex| ex.set("key", "ALREADY_MEMBER")));
+        }
 
         let c: CreatureRelation = sqlx::query_as(
             r##"
             select c.*, ca.user_id, ca.kind from poop.creatures c
                 inner join poop.creature_access ca on ca.creature_id = c.id
             where c.id = $1
+                and ca.user_id = $2
                 and c.deleted is false
                 and ca.deleted is false
             "##,
         )
-        .bind(&c_id.id)
+        .bind(invite.creature_id)
+        .bind(user.id)
+        .fetch_optional(&mut tr)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("creature no longer exists".into()).extend())?;
+        tr.commit().await?;
+        Ok(c)
+    }
+
+    /// Decline a pending invite by its id or the token from the invite email
+    #[graphql(guard = "LoginGuard::session()")]
+    async fn decline_invite(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<String>,
+        token: Option<String>,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.map(|id| id.parse::<i64>()).transpose()?;
+
+        let mut tr = pool.begin().await?;
+        close_invite(&mut tr, user.id, id, token, false)
+            .await
+            .map_err(|e| e.extend())?;
+        tr.commit().await?;
+        Ok(true)
+    }
+
+    /// Give a verified user `pooper` access to a creature between `starts`
+    /// (defaulting to now) and `expires`, replacing any temporary access
+    /// they already have. Like `inviteToCreature`, succeeds whether or not
+    /// the email belongs to a verified user.
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&creature_id, CreatureAccessKind::Creator))"
+    )]
+    async fn grant_temporary_access(
+        &self,
+        ctx: &Context<'_>,
+        creature_id: String,
+        email: String,
+        starts: Option<chrono::DateTime<Utc>>,
+        expires: chrono::DateTime<Utc>,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let creature_id = creature_id.parse::<i64>()?;
+        if expires <= Utc::now() {
+            return Err(AppError::BadRequest("expiration must be in the future".into()).extend());
+        }
+        if starts.map(|s| s >= expires).unwrap_or(false) {
+            return Err(
+                AppError::BadRequest("access must start before it expires".into()).extend(),
+            );
+        }
+
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, creature_id, user.id, CreatureAccessKind::Creator)
+            .await
+            .map_err(|e| e.extend())?;
+        let grantee: Option<User> =
+            sqlx::query_as("select * from poop.users where email = $1 and deleted is false")
+                .bind(email.trim())
+                .fetch_optional(&mut tr)
+                .await?;
+        let grantee = match grantee {
+            Some(grantee) if grantee.verified_at.is_some() => grantee,
+            _ => return Ok(true),
+        };
+
+        clear_temporary_access(&mut tr, creature_id, grantee.id).await?;
+        let member: Option<CreatureMember> = sqlx::query_as(
+            r##"
+            with inserted as (
+                insert into poop.creature_access
+                    (creature_id, user_id, creator_id, kind, starts, expires) values
+                    ($1, $2, $3, 'pooper', $4, $5)
+                on conflict (creature_id, user_id) where deleted is false do nothing
+                returning *
+            )
+            select ca.creature_id, ca.user_id, u.name, ca.kind, ca.starts, ca.expires, ca.created
+                from inserted ca
+                inner join poop.users u on u.id = ca.user_id
+            "##,
+        )
+        .bind(creature_id)
+        .bind(grantee.id)
+        .bind(user.id)
+        .bind(starts)
+        .bind(expires)
+        .fetch_optional(&mut tr)
+        .await?;
+        if member.is_none() {
+            return Err(AppError::BadRequest("user already has access".into())
+                .extend_with(|_, ex| ex.set("key", "ALREADY_MEMBER")));
+        }
+        tr.commit().await?;
+        Ok(true)
+    }
+
+    /// Change the role of a user who already has access to a creature.
+    /// Only the creature's creators can change access, and the last
+    /// creator can't be demoted.
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&creature_id, CreatureAccessKind::Creator))"
+    )]
+    async fn set_creature_access(
+        &self,
+        ctx: &Context<'_>,
+        creature_id: String,
+        user_id: String,
+        kind: CreatureAccessKind,
+    ) -> FieldResult<CreatureMember> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let creature_id = creature_id.parse::<i64>()?;
+        let user_id = user_id.parse::<i64>()?;
+
+        let mut tr = pool.begin().await?;
+        let access = lock_creature_access(&mut tr, creature_id)
+            .await
+            .map_err(|e| e.extend())?;
+        check_creature_role(access.kind(user.id), CreatureAccessKind::Creator)
+            .map_err(|e| e.extend())?;
+        let current = access
+            .kind(user_id)
+            .ok_or_else(|| AppError::BadRequest("user doesn't have access".into()).extend())?;
+        let creators = access.creators();
+        if current == CreatureAccessKind::Creator
+            && kind != CreatureAccessKind::Creator
+            && creators <= 1
+        {
+            return Err(
+                AppError::BadRequest("a creature needs at least one creator".into())
+                    .extend_with(|_, ex| ex.set("key", "LAST_CREATOR")),
+            );
+        }
+        let member: CreatureMember = sqlx::query_as(
+            r##"
+            with updated as (
+                update poop.creature_access set
+                    kind = $3,
+                    -- creators are never temporary
+                    starts = case when $3 = 'creator' then null else starts end,
+                    expires = case when $3 = 'creator' then null else expires end
+                where creature_id = $1
+                    and user_id = $2
+                    and deleted is false
+                    and (starts is null or starts <= now())
+                    and (expires is null or expires > now())
+                returning *
+            )
+            select ca.creature_id, ca.user_id, u.name, ca.kind, ca.starts, ca.expires, ca.created
+                from updated ca
+                inner join poop.users u on u.id = ca.user_id
+            "##,
+        )
+        .bind(creature_id)
+        .bind(user_id)
+        .bind(kind.as_str())
         .fetch_one(&mut tr)
         .await?;
         tr.commit().await?;
-        Ok(c)
+        Ok(member)
     }
 
-    #[graphql(guard = "LoginGuard::new()")]
-    async fn create_poop(&self, ctx: &Context<'_>, creature_id: String) -> FieldResult<Poop> {
+    /// Remove a user's access to a creature. Only the creature's creators
+    /// can remove access, and the last creator can't remove themself.
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&creature_id, CreatureAccessKind::Creator))"
+    )]
+    async fn remove_creature_access(
+        &self,
+        ctx: &Context<'_>,
+        creature_id: String,
+        user_id: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let creature_id = creature_id.parse::<i64>()?;
+        let user_id = user_id.parse::<i64>()?;
+
+        let mut tr = pool.begin().await?;
+        let access = lock_creature_access(&mut tr, creature_id)
+            .await
+            .map_err(|e| e.extend())?;
+        check_creature_role(access.kind(user.id), CreatureAccessKind::Creator)
+            .map_err(|e| e.extend())?;
+        if access.kind(user_id) == Some(CreatureAccessKind::Creator) && access.creators() <= 1 {
+            return Err(
+                AppError::BadRequest("a creature needs at least one creator".into())
+                    .extend_with(|_, ex| ex.set("key", "LAST_CREATOR")),
+            );
+        }
+        // temporary access that hasn't started or has expired can still be removed
+        let res = sqlx::query(
+            r##"
+            update poop.creature_access set deleted = true
+            where creature_id = $1
+                and user_id = $2
+                and deleted is false
+            "##,
+        )
+        .bind(creature_id)
+        .bind(user_id)
+        .execute(&mut tr)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::BadRequest("user doesn't have access".into()).extend());
+        }
+        tr.commit().await?;
+        Ok(true)
+    }
+
+    /// Create a public, read-only link to a creature's recent poops
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&creature_id, CreatureAccessKind::Creator))"
+    )]
+    async fn create_share_link(
+        &self,
+        ctx: &Context<'_>,
+        creature_id: String,
+        name: String,
+        expires: Option<chrono::DateTime<Utc>>,
+    ) -> FieldResult<CreatedShareLink> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let creature_id = creature_id.parse::<i64>()?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::BadRequest("name required".into()).extend());
+        }
+        if expires.map(|e| e <= Utc::now()).unwrap_or(false) {
+            return Err(AppError::BadRequest("expiration must be in the future".into()).extend());
+        }
+        let token = crate::share::new_token()?;
+        let token_hash = crate::crypto::hmac_sign(&token);
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, creature_id, user.id, CreatureAccessKind::Creator)
+            .await
+            .map_err(|e| e.extend())?;
+        let share_link: ShareLink = sqlx::query_as(
+            r##"
+            insert into poop.share_links
+                (creature_id, creator_id, name, hash, expires) values ($1, $2, $3, $4, $5)
+            returning *
+            "##,
+        )
+        .bind(creature_id)
+        .bind(user.id)
+        .bind(name)
+        .bind(token_hash)
+        .bind(expires)
+        .fetch_one(&mut tr)
+        .await?;
+        tr.commit().await?;
+        Ok(CreatedShareLink {
+            url: CONFIG.get_share_url(&token),
+            share_link,
+        })
+    }
+
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&creature_id, CreatureAccessKind::Creator))"
+    )]
+    async fn revoke_share_link(
+        &self,
+        ctx: &Context<'_>,
+        creature_id: String,
+        id: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let creature_id = creature_id.parse::<i64>()?;
+        let id = id.parse::<i64>()?;
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, creature_id, user.id, CreatureAccessKind::Creator)
+            .await
+            .map_err(|e| e.extend())?;
+        let res = sqlx::query(
+            r##"
+            update poop.share_links set deleted = true
+            where id = $1
+                and creature_id = $2
+                and deleted is false
+            "##,
+        )
+        .bind(id)
+        .bind(creature_id)
+        .execute(&mut tr)
+        .await?;
+        tr.commit().await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Log an event of any kind. `attrs` are checked against the kind's schema.
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&creature_id, CreatureAccessKind::Pooper))"
+    )]
+    async fn create_event(
+        &self,
+        ctx: &Context<'_>,
+        creature_id: String,
+        kind: String,
+        attrs: Option<async_graphql::Json<serde_json::Value>>,
+        occurred_at: Option<chrono::DateTime<Utc>>,
+        notes: Option<String>,
+    ) -> FieldResult<Event> {
         let user = ctx.data_unchecked::<User>();
         let pool = ctx.data_unchecked::<PgPool>();
+        let creature_id = creature_id.parse::<i64>()?;
+        let attrs = attrs.map(|a| a.0).unwrap_or_else(|| serde_json::json!({}));
+        let occurred_at = occurred_at.unwrap_or_else(Utc::now);
+        check_occurred_at(occurred_at).map_err(|e| e.extend())?;
+        let notes = notes
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty());
+        if let Some(notes) = &notes {
+            check_notes(notes).map_err(|e| e.extend())?;
+        }
+
+        let event_kind: EventKind = sqlx::query_as("select * from poop.event_kind where kind = $1")
+            .bind(&kind)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| {
+                AppError::BadRequest(format!("unknown event kind {kind}"))
+                    .extend_with(|_, ex| ex.set("key", "UNKNOWN_EVENT_KIND"))
+            })?;
+        if let Err(e) = crate::json_schema::validate(&event_kind.attrs_schema, &attrs) {
+            return Err(AppError::BadRequest(e).extend_with(|_, ex| ex.set("key", "INVALID_ATTRS")));
+        }
+
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, creature_id, user.id, CreatureAccessKind::Pooper)
+            .await
+            .map_err(|e| e.extend())?;
+        let event: Event = sqlx::query_as(
+            r##"
+            insert into poop.events
+                (creator_id, creature_id, kind, occurred_at, attrs, notes)
+                values ($1, $2, $3, $4, $5, $6)
+                returning *
+            "##,
+        )
+        .bind(user.id)
+        .bind(creature_id)
+        .bind(&event_kind.kind)
+        .bind(occurred_at)
+        .bind(Json(attrs))
+        .bind(notes)
+        .fetch_one(&mut tr)
+        .await?;
+        tr.commit().await?;
+        Ok(event)
+    }
 
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&creature_id, CreatureAccessKind::Pooper))"
+    )]
+    async fn create_poop(
+        &self,
+        ctx: &Context<'_>,
+        creature_id: String,
+        input: Option<PoopInput>,
+    ) -> FieldResult<Poop> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
         let creature_id = creature_id.parse::<i64>()?;
+        let input = input.unwrap_or_default();
+        input.validate().map_err(|e| e.extend())?;
 
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, creature_id, user.id, CreatureAccessKind::Pooper)
+            .await
+            .map_err(|e| e.extend())?;
         #[derive(sqlx::FromRow)]
-        struct CId {
+        struct EId {
             id: i64,
         }
+        let e_id: EId = sqlx::query_as(
+            r##"
+            insert into poop.events
+                (creator_id, creature_id, kind)
+                values ($1, $2, 'poop')
+                returning id
+            "##,
+        )
+        .bind(user.id)
+        .bind(creature_id)
+        .fetch_one(&mut tr)
+        .await?;
+        let mut p = load_poop(&mut tr, e_id.id).await?;
+        input.apply(&mut p);
+        let p = save_poop(&mut tr, &p).await?;
+        tr.commit().await?;
+        Ok(p)
+    }
+
+    #[graphql(guard = "LoginGuard::write()")]
+    async fn update_poop(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        input: PoopInput,
+    ) -> FieldResult<Poop> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.parse::<i64>()?;
+        input.validate().map_err(|e| e.extend())?;
+        let mut tr = pool.begin().await?;
+        let mut p = editable_poop(&mut tr, user, id, false).await?;
+        input.apply(&mut p);
+        let p = save_poop(&mut tr, &p).await?;
+        tr.commit().await?;
+        Ok(p)
+    }
 
+    /// Soft-delete a poop, it can be brought back with `restorePoop`
+    #[graphql(guard = "LoginGuard::write()")]
+    async fn delete_poop(&self, ctx: &Context<'_>, id: String) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.parse::<i64>()?;
         let mut tr = pool.begin().await?;
-        let c_id: Option<CId> = sqlx::query_as(
+        editable_poop(&mut tr, user, id, false).await?;
+        let res = sqlx::query(
             r##"
-            select ca.creature_id as id from poop.creature_access ca
-                where ca.creature_id = $1
-                    and ca.user_id = $2
-                    and ca.kind in ('creator', 'pooper')
-                    and ca.deleted is false
+            update poop.events set deleted = true
+            where id = $1
+                and kind = 'poop'
+                and deleted is false
             "##,
         )
-        .bind(&creature_id)
-        .bind(&user.id)
-        .fetch_optional(&mut tr)
+        .bind(id)
+        .execute(&mut tr)
         .await?;
+        tr.commit().await?;
+        Ok(res.rows_affected() > 0)
+    }
 
-        if let Some(c_id) = c_id {
-            let p: Poop = sqlx::query_as(
-                r##"
-            insert into poop.poops
-                (creator_id, creature_id)
-                values ($1, $2)
-                returning *
+    /// Undo a `deletePoop`
+    #[graphql(guard = "LoginGuard::write()")]
+    async fn restore_poop(&self, ctx: &Context<'_>, id: String) -> FieldResult<Poop> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let id = id.parse::<i64>()?;
+        let mut tr = pool.begin().await?;
+        editable_poop(&mut tr, user, id, true).await?;
+        sqlx::query(
+            r##"
+            update poop.events set deleted = false
+            where id = $1
+                and kind = 'poop'
             "##,
-            )
-            .bind(&user.id)
-            .bind(&c_id.id)
-            .fetch_one(&mut tr)
-            .await?;
+        )
+        .bind(id)
+        .execute(&mut tr)
+        .await?;
+        let p = load_poop(&mut tr, id).await?;
+        tr.commit().await?;
+        Ok(p)
+    }
 
-            tr.commit().await?;
-            Ok(p)
-        } else {
-            Err(AppError::Unauthorized(format!(
-                "user {} doesn't have poop creation clearance for creature {}",
-                user.id, creature_id
-            ))
-            .extend())
+    #[graphql(
+        guard = "LoginGuard::write().and(CreatureRoleGuard::new(&creature_id, CreatureAccessKind::Pooper))"
+    )]
+    async fn record_weight(
+        &self,
+        ctx: &Context<'_>,
+        creature_id: String,
+        value: f64,
+        unit: WeightUnit,
+        measured_at: Option<chrono::DateTime<Utc>>,
+    ) -> FieldResult<Weight> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let creature_id = creature_id.parse::<i64>()?;
+        if !value.is_finite() || value <= 0.0 {
+            return Err(AppError::BadRequest("value must be greater than 0".into()).extend());
+        }
+        if let Some(measured_at) = measured_at {
+            check_measured_at(measured_at).map_err(|e| e.extend())?;
+        }
+
+        #[derive(sqlx::FromRow)]
+        struct WId {
+            id: i64,
         }
+        let mut tr = pool.begin().await?;
+        require_creature_role(&mut tr, creature_id, user.id, CreatureAccessKind::Pooper)
+            .await
+            .map_err(|e| e.extend())?;
+        let w_id: WId = sqlx::query_as(
+            r##"
+            insert into poop.weights
+                (creator_id, creature_id, measured_at, value, unit) values
+                ($1, $2, coalesce($3, now()), $4, $5)
+            returning id
+            "##,
+        )
+        .bind(user.id)
+        .bind(creature_id)
+        .bind(measured_at)
+        .bind(value)
+        .bind(unit.as_str())
+        .fetch_one(&mut tr)
+        .await?;
+        let w = load_weight(&mut tr, w_id.id).await?;
+        tr.commit().await?;
+        Ok(w)
     }
 }
 
@@ -248,6 +2307,44 @@ impl QueryRoot {
         let u = ctx.data_opt::<User>();
         u.cloned()
     }
+
+    /// The kinds of events that can be logged
+    #[graphql(guard = "LoginGuard::new()")]
+    async fn event_kinds(&self, ctx: &Context<'_>) -> FieldResult<Vec<EventKind>> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let kinds: Vec<EventKind> = sqlx::query_as("select * from poop.event_kind order by kind")
+            .fetch_all(pool)
+            .await?;
+        Ok(kinds)
+    }
+
+    /// The species a creature's profile can be set to
+    #[graphql(guard = "LoginGuard::new()")]
+    async fn species(&self, ctx: &Context<'_>) -> FieldResult<Vec<Species>> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let species: Vec<Species> = sqlx::query_as("select * from poop.species order by name")
+            .fetch_all(pool)
+            .await?;
+        Ok(species)
+    }
 }
 
 pub type Schema = async_graphql::Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encode_leaves_unreserved() {
+        assert_eq!(percent_encode("AZaz09-._~"), "AZaz09-._~");
+        assert_eq!(percent_encode(""), "");
+    }
+
+    #[test]
+    fn percent_encode_escapes_everything_else() {
+        assert_eq!(percent_encode("a b&c=d/e:f"), "a%20b%26c%3Dd%2Fe%3Af");
+        assert_eq!(percent_encode("didpoop:u@x.com"), "didpoop%3Au%40x.com");
+        assert_eq!(percent_encode("é"), "%C3%A9");
+    }
+}
@@ -0,0 +1,204 @@
+/*!
+Public, read-only share links for a creature's poop log
+*/
+use crate::{crypto, Result, CONFIG};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::convert::Infallible;
+use warp::http::StatusCode;
+
+/// How far back a shared log goes
+const SHARE_HISTORY_DAYS: i64 = 30;
+const SHARE_HISTORY_LIMIT: i64 = 200;
+
+/// A new share link token, `<nonce>.<signature>`
+pub fn new_token() -> Result<String> {
+    let nonce = hex::encode(crypto::rand_bytes(24)?);
+    let sig = crypto::hmac_sign_with_key(&nonce, &CONFIG.share_link_signing_key);
+    Ok(format!("{nonce}.{sig}"))
+}
+
+/// Reject forged tokens before they're ever looked up
+fn verify_token(token: &str) -> bool {
+    match token.split_once('.') {
+        Some((nonce, sig)) => {
+            crypto::hmac_verify_with_key(nonce, sig, &CONFIG.share_link_signing_key)
+        }
+        None => false,
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#x27;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[derive(sqlx::FromRow)]
+struct SharedCreature {
+    creature_id: i64,
+    name: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct SharedPoop {
+    occurred_at: DateTime<Utc>,
+    bristol: Option<i16>,
+    color: Option<String>,
+    size: Option<String>,
+    blood: bool,
+    mucus: bool,
+    notes: Option<String>,
+}
+
+/// The shared creature and its recent poops, if the token is for a live link
+async fn load(pool: &PgPool, token: &str) -> Result<Option<(SharedCreature, Vec<SharedPoop>)>> {
+    if !verify_token(token) {
+        return Ok(None);
+    }
+    let creature: Option<SharedCreature> = sqlx::query_as(
+        r##"
+        update poop.share_links sl set last_viewed = now()
+        from poop.creatures c
+        where sl.hash = $1
+            and c.id = sl.creature_id
+            and sl.deleted is false
+            and (sl.expires is null or sl.expires > now())
+            and c.deleted is false
+        returning sl.creature_id, c.name
+        "##,
+    )
+    .bind(crypto::hmac_sign(token))
+    .fetch_optional(pool)
+    .await?;
+    let creature = match creature {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+    let poops: Vec<SharedPoop> = sqlx::query_as(
+        r##"
+        select p.occurred_at, p.bristol, p.color, p.size, p.blood, p.mucus, p.notes
+            from poop.poops p
+        where p.creature_id = $1
+            and p.deleted is false
+            and p.occurred_at > now() - make_interval(days => $2::int)
+            order by p.occurred_at desc
+            limit $3
+        "##,
+    )
+    .bind(creature.creature_id)
+    .bind(SHARE_HISTORY_DAYS as i32)
+    .bind(SHARE_HISTORY_LIMIT)
+    .fetch_all(pool)
+    .await?;
+    Ok(Some((creature, poops)))
+}
+
+fn render_page(title: &str, content: &str) -> String {
+    format!(
+        r##"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>{title}</title>
+</head>
+<body>
+{content}
+</body>
+</html>
+"##,
+        title = escape_html(title),
+        content = content,
+    )
+}
+
+fn render_log(creature: &SharedCreature, poops: &[SharedPoop]) -> String {
+    let mut content = format!(
+        "<h1>{name}</h1>\n<p>Poops from the last {days} days</p>\n",
+        name = escape_html(&creature.name),
+        days = SHARE_HISTORY_DAYS,
+    );
+    if poops.is_empty() {
+        content.push_str("<p>Nothing logged</p>\n");
+    } else {
+        content.push_str(concat!(
+            "<table>\n<tr><th>When</th><th>Bristol</th><th>Color</th><th>Size</th>",
+            "<th>Blood</th><th>Mucus</th><th>Notes</th></tr>\n",
+        ));
+        for p in poops {
+            let flag = |b: bool| if b { "yes" } else { "" };
+            content.push_str(&format!(
+                concat!(
+                    "<tr><td>{when}</td><td>{bristol}</td><td>{color}</td><td>{size}</td>",
+                    "<td>{blood}</td><td>{mucus}</td><td>{notes}</td></tr>\n",
+                ),
+                when = p.occurred_at.format("%Y-%m-%d %H:%M UTC"),
+                bristol = p.bristol.map(|b| b.to_string()).unwrap_or_default(),
+                color = escape_html(&p.color.as_deref().unwrap_or("").replace('_', " ")),
+                size = escape_html(p.size.as_deref().unwrap_or("")),
+                blood = flag(p.blood),
+                mucus = flag(p.mucus),
+                notes = escape_html(p.notes.as_deref().unwrap_or("")),
+            ));
+        }
+        content.push_str("</table>\n");
+    }
+    render_page(&format!("{} - didpoop", creature.name), &content)
+}
+
+/// Public page showing the log behind a share link
+pub async fn page(
+    token: String,
+    pool: PgPool,
+) -> std::result::Result<impl warp::Reply, Infallible> {
+    let (status, body) = match load(&pool, &token).await {
+        Ok(Some((creature, poops))) => (StatusCode::OK, render_log(&creature, &poops)),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            render_page(
+                "Not found",
+                "<p>This link is invalid or has been revoked</p>",
+            ),
+        ),
+        Err(e) => {
+            tracing::error!(error = ?e, "error loading share link");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                render_page("Error", "<p>Something went wrong</p>"),
+            )
+        }
+    };
+    let reply = warp::reply::with_status(warp::reply::html(body), status);
+    Ok(warp::reply::with_header(reply, "cache-control", "no-store"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_html_escapes_markup() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#x27;Jerry&#x27;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn escape_html_leaves_plain_text() {
+        assert_eq!(escape_html(""), "");
+        assert_eq!(escape_html("Rex the dög"), "Rex the dög");
+        assert_eq!(escape_html("&amp;"), "&amp;amp;");
+    }
+}
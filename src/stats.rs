@@ -0,0 +1,317 @@
+/*!
+Per-creature poop statistics
+*/
+use crate::models::{DailyCount, PoopStats, StatsWindow, WindowStats};
+use crate::{AppError, Result};
+use cached::proc_macro::cached;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+/// Identifies the stats of a closed window. `count` and `modified` cover
+/// every poop, deleted or not, that occurred in the window or the one
+/// before it, so any change to those poops makes a new key.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct StatsKey {
+    creature_id: i64,
+    window: StatsWindow,
+    end: DateTime<Utc>,
+    count: i64,
+    modified: Option<DateTime<Utc>>,
+}
+
+/// Stats for the `window` ending at `end` and the one before it. `end` is
+/// rounded up to a UTC day boundary, so a window always covers whole days.
+///
+/// Windows that have already ended are memoized. The fingerprint query
+/// still runs on every call (a range scan of the creature's poop index),
+/// the cache saves the interval stats and daily count queries.
+pub async fn poop_stats(
+    pool: &PgPool,
+    creature_id: i64,
+    window: StatsWindow,
+    end: DateTime<Utc>,
+) -> Result<PoopStats> {
+    let end = day_end(end);
+    if end >= Utc::now() {
+        return load(pool, creature_id, window, end).await;
+    }
+
+    #[derive(sqlx::FromRow)]
+    struct Fingerprint {
+        count: i64,
+        modified: Option<DateTime<Utc>>,
+    }
+    let fp: Fingerprint = sqlx::query_as(
+        r##"
+        select count(*) as count, max(modified) as modified from poop.poops
+        where creature_id = $1
+            and occurred_at >= $2
+            and occurred_at < $3
+        "##,
+    )
+    .bind(creature_id)
+    .bind(end - window.duration() * 2)
+    .bind(end)
+    .fetch_one(pool)
+    .await?;
+    let key = StatsKey {
+        creature_id,
+        window,
+        end,
+        count: fp.count,
+        modified: fp.modified,
+    };
+    closed_window_stats(pool, &key).await
+}
+
+/// The first UTC midnight at or after `at`
+fn day_end(at: DateTime<Utc>) -> DateTime<Utc> {
+    let day = at.date().and_hms(0, 0, 0);
+    if day == at {
+        day
+    } else {
+        day + chrono::Duration::days(1)
+    }
+}
+
+#[cached(
+    size = 1000,
+    result = true,
+    key = "StatsKey",
+    convert = r#"{ stats_key.clone() }"#
+)]
+async fn closed_window_stats(pool: &PgPool, stats_key: &StatsKey) -> Result<PoopStats> {
+    load(pool, stats_key.creature_id, stats_key.window, stats_key.end).await
+}
+
+async fn load(
+    pool: &PgPool,
+    creature_id: i64,
+    window: StatsWindow,
+    end: DateTime<Utc>,
+) -> Result<PoopStats> {
+    let starts = end - window.duration();
+    let previous_starts = starts - window.duration();
+
+    // intervals are between consecutive poops within the same window
+    let mut windows: Vec<WindowStats> = sqlx::query_as(
+        r##"
+        with w as (
+            select * from (values
+                (false, $2::timestamptz, $3::timestamptz),
+                (true, $3::timestamptz, $4::timestamptz)
+            ) as w(current, starts, ends)
+        ), i as (
+            select w.current, p.occurred_at,
+                lag(p.occurred_at) over win as prev_occurred_at,
+                extract(epoch from p.occurred_at - lag(p.occurred_at) over win)::double precision
+                    as interval_seconds
+            from w
+                inner join poop.poops p
+                    on p.occurred_at >= w.starts
+                    and p.occurred_at < w.ends
+            where p.creature_id = $1
+                and p.deleted is false
+            window win as (partition by w.current order by p.occurred_at, p.id)
+        )
+        select w.starts, w.ends,
+            count(i.occurred_at) as count,
+            avg(i.interval_seconds) as mean_interval_seconds,
+            percentile_cont(0.5) within group (order by i.interval_seconds)
+                as median_interval_seconds,
+            max(i.interval_seconds) as longest_gap_seconds,
+            (array_agg(i.prev_occurred_at order by i.interval_seconds desc)
+                filter (where i.interval_seconds is not null))[1] as longest_gap_from,
+            (array_agg(i.occurred_at order by i.interval_seconds desc)
+                filter (where i.interval_seconds is not null))[1] as longest_gap_to
+        from w
+            left join i on i.current = w.current
+        group by w.current, w.starts, w.ends
+        order by w.current
+        "##,
+    )
+    .bind(creature_id)
+    .bind(previous_starts)
+    .bind(starts)
+    .bind(end)
+    .fetch_all(pool)
+    .await?;
+    let current = windows
+        .pop()
+        .ok_or_else(|| AppError::E("missing current stats window".into()))?;
+    let previous = windows
+        .pop()
+        .ok_or_else(|| AppError::E("missing previous stats window".into()))?;
+
+    let daily_counts: Vec<DailyCount> = sqlx::query_as(
+        r##"
+        select d.day::date as day, count(p.id) as count
+        from generate_series(
+            date_trunc('day', $2 at time zone 'utc'),
+            ($3 - interval '1 microsecond') at time zone 'utc',
+            interval '1 day'
+        ) as d(day)
+            left join poop.poops p
+                on p.creature_id = $1
+                and p.deleted is false
+                and p.occurred_at >= $2
+                and p.occurred_at < $3
+                and date_trunc('day', p.occurred_at at time zone 'utc') = d.day
+        group by d.day
+        order by d.day
+        "##,
+    )
+    .bind(creature_id)
+    .bind(starts)
+    .bind(end)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(PoopStats {
+        window,
+        current,
+        previous,
+        daily_counts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    async fn insert_creature(pool: &PgPool) -> Result<(i64, i64)> {
+        #[derive(sqlx::FromRow)]
+        struct Id {
+            id: i64,
+        }
+        let user: Id = sqlx::query_as(
+            r##"
+            insert into poop.users (email, name, pw_salt, pw_hash, pw_params)
+                values ('stats-' || poop.id_gen() || '@test', 'stats', '', '', '{}')
+                returning id
+            "##,
+        )
+        .fetch_one(pool)
+        .await?;
+        let creature: Id = sqlx::query_as(
+            "insert into poop.creatures (creator_id, name) values ($1, 'stats') returning id",
+        )
+        .bind(user.id)
+        .fetch_one(pool)
+        .await?;
+        Ok((user.id, creature.id))
+    }
+
+    async fn insert_poop(
+        pool: &PgPool,
+        user_id: i64,
+        creature_id: i64,
+        occurred_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            r##"
+            insert into poop.events (creator_id, creature_id, kind, occurred_at)
+                values ($1, $2, 'poop', $3)
+            "##,
+        )
+        .bind(user_id)
+        .bind(creature_id)
+        .bind(occurred_at)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_creature(pool: &PgPool, user_id: i64, creature_id: i64) -> Result<()> {
+        sqlx::query("delete from poop.events where creature_id = $1")
+            .bind(creature_id)
+            .execute(pool)
+            .await?;
+        sqlx::query("delete from poop.creature_access where creature_id = $1")
+            .bind(creature_id)
+            .execute(pool)
+            .await?;
+        sqlx::query("delete from poop.creatures where id = $1")
+            .bind(creature_id)
+            .execute(pool)
+            .await?;
+        sqlx::query("delete from poop.users where id = $1")
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn day_end_rounds_up_to_midnight() {
+        let midnight = Utc.ymd(2000, 1, 2).and_hms(0, 0, 0);
+        assert_eq!(day_end(midnight), midnight);
+        assert_eq!(
+            day_end(Utc.ymd(2000, 1, 1).and_hms_micro(0, 0, 0, 1)),
+            midnight
+        );
+        assert_eq!(day_end(Utc.ymd(2000, 1, 1).and_hms(23, 59, 59)), midnight);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a migrated database"]
+    async fn load_windows() -> Result<()> {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL not set");
+        let pool = PgPool::connect(&url).await?;
+        let (user_id, creature_id) = insert_creature(&pool).await?;
+        let at = |d, h| Utc.ymd(2000, 1, d).and_hms(h, 0, 0);
+        // one poop in the previous week, three in the current one
+        for occurred_at in [at(3, 12), at(9, 0), at(9, 6), at(10, 6)] {
+            insert_poop(&pool, user_id, creature_id, occurred_at).await?;
+        }
+        let end = at(15, 0);
+        let stats = load(&pool, creature_id, StatsWindow::Week, end).await;
+        let empty = load(
+            &pool,
+            creature_id,
+            StatsWindow::Week,
+            end - StatsWindow::Week.duration() * 2,
+        )
+        .await;
+        delete_creature(&pool, user_id, creature_id).await?;
+        let (stats, empty) = (stats?, empty?);
+
+        let current = &stats.current;
+        assert_eq!((current.starts, current.ends), (at(8, 0), end));
+        assert_eq!(current.count, 3);
+        assert_eq!(current.mean_interval_seconds, Some(15.0 * 3600.0));
+        assert_eq!(current.median_interval_seconds, Some(15.0 * 3600.0));
+        assert_eq!(current.longest_gap_seconds, Some(24.0 * 3600.0));
+        assert_eq!(current.longest_gap_from, Some(at(9, 6)));
+        assert_eq!(current.longest_gap_to, Some(at(10, 6)));
+
+        // a single poop has no interval, so no gap either
+        let previous = &stats.previous;
+        assert_eq!((previous.starts, previous.ends), (at(1, 0), at(8, 0)));
+        assert_eq!(previous.count, 1);
+        assert_eq!(previous.mean_interval_seconds, None);
+        assert_eq!(previous.longest_gap_seconds, None);
+        assert_eq!(previous.longest_gap_from, None);
+        assert_eq!(previous.longest_gap_to, None);
+        assert_eq!(stats.current.change_from(previous), Some(2.0));
+
+        let counts = stats
+            .daily_counts
+            .iter()
+            .map(|d| d.count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [0, 2, 1, 0, 0, 0, 0]);
+
+        for w in [&empty.current, &empty.previous] {
+            assert_eq!(w.count, 0);
+            assert_eq!(w.mean_interval_seconds, None);
+            assert_eq!(w.median_interval_seconds, None);
+            assert_eq!(w.longest_gap_from, None);
+            assert_eq!(w.longest_gap_to, None);
+        }
+        assert_eq!(empty.current.change_from(&empty.previous), None);
+        assert_eq!(empty.daily_counts.len(), 7);
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+/*!
+Failed login tracking and lockouts
+*/
+use crate::{AppError, Result, CONFIG};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Past this many tracked keys, the memory store drops keys
+/// whose failures have aged out
+const MEMORY_STORE_PRUNE_SIZE: usize = 10_000;
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Attempts {
+    pub failures: i32,
+    pub last_failure: DateTime<Utc>,
+}
+
+/// How many failures a key is allowed before it's locked out, and for how long
+#[derive(Clone, Copy, Debug)]
+pub struct Lockout {
+    pub max_failures: i32,
+    pub seconds: i64,
+    pub max_seconds: i64,
+}
+impl Lockout {
+    /// When `attempts` keeps its key locked out until, if at all. Once the
+    /// max is reached, each further failure doubles the lockout.
+    fn until(&self, attempts: &Attempts) -> Option<DateTime<Utc>> {
+        let over = attempts.failures - self.max_failures;
+        if over < 0 {
+            return None;
+        }
+        let seconds = self
+            .seconds
+            .saturating_mul(1 << over.min(30))
+            .min(self.max_seconds);
+        Some(attempts.last_failure + Duration::seconds(seconds))
+    }
+
+    /// Count an attempt on top of `attempts` unless they're locked out,
+    /// returning when the lockout ends instead
+    fn count(
+        &self,
+        attempts: &mut Attempts,
+        now: DateTime<Utc>,
+        window: Duration,
+    ) -> Option<DateTime<Utc>> {
+        if let Some(until) = self.until(attempts).filter(|until| *until > now) {
+            return Some(until);
+        }
+        if attempts.last_failure < now - window {
+            attempts.failures = 0;
+        }
+        attempts.failures += 1;
+        attempts.last_failure = now;
+        None
+    }
+}
+
+#[async_trait::async_trait]
+pub trait AttemptStore: Send + Sync {
+    /// Count an attempt against `key` as a failure up front, unless it's
+    /// locked out, in which case when the lockout ends is returned. The
+    /// check and the count are one step so that concurrent attempts can't
+    /// all get past the check. The count starts over when the previous
+    /// failure is older than `window`.
+    async fn attempt(
+        &self,
+        key: &str,
+        window: Duration,
+        lockout: &Lockout,
+    ) -> Result<Option<DateTime<Utc>>>;
+
+    /// Take back an attempt that didn't fail
+    async fn refund(&self, key: &str) -> Result<()>;
+
+    async fn reset(&self, key: &str) -> Result<()>;
+}
+
+/// Tracks attempts in this process only
+#[derive(Default)]
+pub struct MemoryAttemptStore {
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+#[async_trait::async_trait]
+impl AttemptStore for MemoryAttemptStore {
+    async fn attempt(
+        &self,
+        key: &str,
+        window: Duration,
+        lockout: &Lockout,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let now = Utc::now();
+        let mut attempts = self.attempts.lock().map_err(|_| "attempts lock poisoned")?;
+        if attempts.len() >= MEMORY_STORE_PRUNE_SIZE {
+            let cutoff = now - window;
+            attempts.retain(|_, a| a.last_failure >= cutoff);
+        }
+        let a = attempts.entry(key.to_string()).or_insert(Attempts {
+            failures: 0,
+            last_failure: now,
+        });
+        Ok(lockout.count(a, now, window))
+    }
+
+    async fn refund(&self, key: &str) -> Result<()> {
+        let mut attempts = self.attempts.lock().map_err(|_| "attempts lock poisoned")?;
+        if let Some(a) = attempts.get_mut(key) {
+            a.failures = (a.failures - 1).max(0);
+        }
+        Ok(())
+    }
+
+    async fn reset(&self, key: &str) -> Result<()> {
+        let mut attempts = self.attempts.lock().map_err(|_| "attempts lock poisoned")?;
+        attempts.remove(key);
+        Ok(())
+    }
+}
+
+/// Tracks attempts in `poop.login_attempts` so they're shared across replicas
+pub struct PgAttemptStore {
+    pool: PgPool,
+}
+impl PgAttemptStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl AttemptStore for PgAttemptStore {
+    async fn attempt(
+        &self,
+        key: &str,
+        window: Duration,
+        lockout: &Lockout,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let mut tr = self.pool.begin().await?;
+        sqlx::query(
+            r##"
+            insert into poop.login_attempts
+                (key, failures, last_failure) values ($1, 0, now())
+            on conflict (key) do nothing
+            "##,
+        )
+        .bind(key)
+        .execute(&mut tr)
+        .await?;
+        // the row lock serializes concurrent attempts on the same key
+        let mut a: Attempts = sqlx::query_as(
+            "select failures, last_failure from poop.login_attempts where key = $1 for update",
+        )
+        .bind(key)
+        .fetch_one(&mut tr)
+        .await?;
+        let locked = lockout.count(&mut a, Utc::now(), window);
+        if locked.is_none() {
+            sqlx::query(
+                "update poop.login_attempts set failures = $2, last_failure = $3 where key = $1",
+            )
+            .bind(key)
+            .bind(a.failures)
+            .bind(a.last_failure)
+            .execute(&mut tr)
+            .await?;
+        }
+        tr.commit().await?;
+        Ok(locked)
+    }
+
+    async fn refund(&self, key: &str) -> Result<()> {
+        sqlx::query(
+            r##"
+            update poop.login_attempts set failures = greatest(failures - 1, 0)
+            where key = $1
+            "##,
+        )
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn reset(&self, key: &str) -> Result<()> {
+        sqlx::query("delete from poop.login_attempts where key = $1")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// What an attempt is being tracked by, each kind has its own limit
+#[derive(Clone, Copy, Debug)]
+pub enum AttemptKind {
+    Email,
+    Ip,
+}
+impl AttemptKind {
+    fn key(self, value: &str) -> String {
+        match self {
+            Self::Email => format!("email:{}", value.to_lowercase()),
+            Self::Ip => format!("ip:{value}"),
+        }
+    }
+    fn lockout(self) -> Lockout {
+        Lockout {
+            max_failures: match self {
+                Self::Email => CONFIG.login_max_failures_per_email,
+                Self::Ip => CONFIG.login_max_failures_per_ip,
+            },
+            seconds: CONFIG.login_lockout_seconds as i64,
+            max_seconds: CONFIG.login_lockout_max_seconds as i64,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Throttle {
+    store: Arc<dyn AttemptStore>,
+}
+impl Throttle {
+    /// Build a throttle backed by the store selected by `CONFIG.login_attempt_store`
+    pub fn from_config(pool: PgPool) -> Result<Self> {
+        let store: Arc<dyn AttemptStore> = match CONFIG.login_attempt_store.as_str() {
+            "memory" => Arc::new(MemoryAttemptStore::default()),
+            "postgres" => Arc::new(PgAttemptStore::new(pool)),
+            other => return Err(format!("invalid login attempt store: {other}").into()),
+        };
+        Ok(Self { store })
+    }
+
+    /// Count a login attempt against each of the keys, failing with
+    /// `AppError::LockedOut` if any of them are locked out. Attempts are
+    /// counted as failures until they're refunded.
+    pub async fn attempt(&self, keys: &[(AttemptKind, &str)]) -> Result<()> {
+        let window = Duration::seconds(CONFIG.login_attempt_window_seconds as i64);
+        for (i, (kind, value)) in keys.iter().enumerate() {
+            let locked = self
+                .store
+                .attempt(&kind.key(value), window, &kind.lockout())
+                .await?;
+            if let Some(until) = locked {
+                tracing::warn!(kind = ?kind, value = %value, until = %until, "login locked out");
+                self.refund(&keys[..i]).await?;
+                let seconds = (until - Utc::now()).num_seconds().max(1);
+                return Err(AppError::LockedOut(seconds));
+            }
+        }
+        Ok(())
+    }
+
+    /// Take back attempts that didn't fail
+    pub async fn refund(&self, keys: &[(AttemptKind, &str)]) -> Result<()> {
+        for (kind, value) in keys {
+            self.store.refund(&kind.key(value)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn reset(&self, keys: &[(AttemptKind, &str)]) -> Result<()> {
+        for (kind, value) in keys {
+            self.store.reset(&kind.key(value)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKOUT: Lockout = Lockout {
+        max_failures: 3,
+        seconds: 60,
+        max_seconds: 300,
+    };
+
+    #[test]
+    fn lockout_doubles_up_to_max() {
+        let now = Utc::now();
+        let attempts = |failures| Attempts {
+            failures,
+            last_failure: now,
+        };
+        assert_eq!(LOCKOUT.until(&attempts(2)), None);
+        assert_eq!(
+            LOCKOUT.until(&attempts(3)),
+            Some(now + Duration::seconds(60))
+        );
+        assert_eq!(
+            LOCKOUT.until(&attempts(4)),
+            Some(now + Duration::seconds(120))
+        );
+        assert_eq!(
+            LOCKOUT.until(&attempts(5)),
+            Some(now + Duration::seconds(240))
+        );
+        assert_eq!(
+            LOCKOUT.until(&attempts(6)),
+            Some(now + Duration::seconds(300))
+        );
+        assert_eq!(
+            LOCKOUT.until(&attempts(100)),
+            Some(now + Duration::seconds(300))
+        );
+    }
+
+    #[test]
+    fn count_starts_over_after_window() {
+        let now = Utc::now();
+        let window = Duration::minutes(15);
+        let mut a = Attempts {
+            failures: 2,
+            last_failure: now - Duration::minutes(16),
+        };
+        assert_eq!(LOCKOUT.count(&mut a, now, window), None);
+        assert_eq!(a.failures, 1);
+        assert_eq!(a.last_failure, now);
+
+        // an expired lockout lets the next attempt through
+        let mut a = Attempts {
+            failures: 3,
+            last_failure: now - Duration::minutes(2),
+        };
+        assert_eq!(LOCKOUT.count(&mut a, now, window), None);
+        assert_eq!(a.failures, 4);
+        let until = LOCKOUT.count(&mut a, now, window);
+        assert_eq!(until, Some(now + Duration::seconds(120)));
+        assert_eq!(a.failures, 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_attempts_are_counted_once_each() {
+        let store = Arc::new(MemoryAttemptStore::default());
+        let window = Duration::minutes(15);
+        let tasks = (0..20)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move { store.attempt("email:a@x.com", window, &LOCKOUT).await })
+            })
+            .collect::<Vec<_>>();
+        let mut allowed = 0;
+        for task in tasks {
+            if task.await.unwrap().unwrap().is_none() {
+                allowed += 1;
+            }
+        }
+        assert_eq!(allowed, LOCKOUT.max_failures);
+    }
+
+    #[tokio::test]
+    async fn refund_and_reset() {
+        let store = MemoryAttemptStore::default();
+        let window = Duration::minutes(15);
+        for _ in 0..3 {
+            assert_eq!(store.attempt("k", window, &LOCKOUT).await.unwrap(), None);
+        }
+        assert!(store
+            .attempt("k", window, &LOCKOUT)
+            .await
+            .unwrap()
+            .is_some());
+        store.refund("k").await.unwrap();
+        assert_eq!(store.attempt("k", window, &LOCKOUT).await.unwrap(), None);
+        store.reset("k").await.unwrap();
+        assert_eq!(store.attempt("k", window, &LOCKOUT).await.unwrap(), None);
+        // refunding never goes below zero
+        store.refund("k").await.unwrap();
+        store.refund("k").await.unwrap();
+        for _ in 0..3 {
+            assert_eq!(store.attempt("k", window, &LOCKOUT).await.unwrap(), None);
+        }
+    }
+}